@@ -1,28 +1,505 @@
-use std::collections::BTreeMap;
+use crate::{
+    codec::{Decode, Encode, Error as CodecError},
+    merkle::{self, Hash, MerkleProof},
+    storage::{OverlayedLog, Storage, StorageMap, StorageValue, Transactional},
+    support::{DispatchResultWithPostInfo, GetDispatchInfo, PostDispatchInfo, Weight},
+};
+use core::fmt::Debug;
+use num_traits::{CheckedAdd, CheckedMul, CheckedSub, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The configuration trait for the Balances Pallet.
+/// Contains the basic types needed for handling balances, on top of the ones coming from the
+/// System Pallet.
+pub trait Config: crate::system::Config {
+    /// A type which can represent the balance of an account.
+    /// Usually this is a large unsigned integer.
+    /// It must be able to represent any [`Weight`], which fees are charged for.
+    type Balance: Zero
+        + CheckedSub
+        + CheckedAdd
+        + CheckedMul
+        + From<Weight>
+        + Copy
+        + Ord
+        + Debug
+        + Encode
+        + Decode;
+
+    /// The minimum balance an account must hold to exist. Accounts whose balance drops to zero are
+    /// reaped, and no operation may leave an account with a non-zero balance below this amount.
+    const EXISTENTIAL_DEPOSIT: Self::Balance;
+}
+
+/// The errors which can be returned by the Balances Pallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The account does not have enough balance for the operation.
+    InsufficientBalance,
+    /// An arithmetic operation on a balance overflowed.
+    Overflow,
+    /// The operation would leave an account with less than the existential deposit.
+    BelowExistentialDeposit,
+    /// The operation would spend balance which is frozen by a lock.
+    LiquidityRestrictions,
+    /// The spender is not allowed to spend that much on behalf of the owner.
+    InsufficientAllowance,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            Error::InsufficientBalance => "Not enough funds.",
+            Error::Overflow => "Overflow error.",
+            Error::BelowExistentialDeposit => "Balance would fall below the existential deposit.",
+            Error::LiquidityRestrictions => "Balance is frozen by a lock.",
+            Error::InsufficientAllowance => "Allowance exceeded.",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The events emitted by the Balances Pallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T: Config> {
+    /// An account was created with some initial balance.
+    Endowed { account: T::AccountId, free_balance: T::Balance },
+    /// `amount` was transferred from `from` to `to`.
+    Transfer { from: T::AccountId, to: T::AccountId, amount: T::Balance },
+    /// `amount` was created and credited to `who`.
+    Minted { who: T::AccountId, amount: T::Balance },
+    /// `amount` was debited from `who` and destroyed.
+    Burned { who: T::AccountId, amount: T::Balance },
+    /// An account was removed because its balance dropped to zero.
+    Reaped { account: T::AccountId },
+    /// `amount` was moved from the free to the reserved balance of `who`.
+    Reserved { who: T::AccountId, amount: T::Balance },
+    /// `amount` was moved from the reserved to the free balance of `who`.
+    Unreserved { who: T::AccountId, amount: T::Balance },
+    /// `amount` was debited from the reserved balance of `who` and destroyed.
+    Slashed { who: T::AccountId, amount: T::Balance },
+    /// `amount` of reserved balance was moved from `from` to `to`, and credited to the part of its
+    /// balance given by `destination_status`.
+    ReserveRepatriated {
+        from: T::AccountId,
+        to: T::AccountId,
+        amount: T::Balance,
+        destination_status: BalanceStatus,
+    },
+    /// A lock `id` freezing `amount` of the balance of `who` until block `until` was set.
+    LockSet { who: T::AccountId, id: LockIdentifier, amount: T::Balance, until: T::BlockNumber },
+    /// The lock `id` on the balance of `who` was removed.
+    LockRemoved { who: T::AccountId, id: LockIdentifier },
+    /// `owner` allowed `spender` to transfer up to `amount` on its behalf.
+    Approval { owner: T::AccountId, spender: T::AccountId, amount: T::Balance },
+    /// `who` paid `amount` of fees for an extrinsic, to `treasury`, or burned if `None`.
+    FeePaid { who: T::AccountId, amount: T::Balance, treasury: Option<T::AccountId> },
+}
+
+/// The balance of an account, split between the part the account can freely spend and the part
+/// which is held aside by other modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountData<Balance> {
+    /// The balance which the account can spend.
+    pub free: Balance,
+    /// The balance which is held aside, e.g. as a deposit, and cannot be spent.
+    pub reserved: Balance,
+}
+
+impl<Balance: Zero + CheckedAdd> AccountData<Balance> {
+    /// The total balance of the account, i.e. free plus reserved.
+    pub fn total(&self) -> Option<Balance> {
+        self.free.checked_add(&self.reserved)
+    }
+}
+
+impl<Balance: Encode> Encode for AccountData<Balance> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.free.encode_to(dest);
+        self.reserved.encode_to(dest);
+    }
+}
+
+impl<Balance: Decode> Decode for AccountData<Balance> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { free: Balance::decode(input)?, reserved: Balance::decode(input)? })
+    }
+}
+
+/// The part of the beneficiary's balance which repatriated reserved funds are credited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceStatus {
+    /// The funds become free, i.e. spendable by the beneficiary.
+    Free,
+    /// The funds stay reserved on the beneficiary's account.
+    Reserved,
+}
+
+/// The identifier of a lock, chosen by the module which sets it, e.g. `*b"vesting "`.
+pub type LockIdentifier = [u8; 8];
+
+/// A lock freezing part of the free balance of an account, without moving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceLock<Balance, BlockNumber> {
+    /// The amount of free balance which cannot be spent while the lock is active.
+    pub amount: Balance,
+    /// The last block number at which the lock is active.
+    pub until: BlockNumber,
+}
+
+impl<Balance: Encode, BlockNumber: Encode> Encode for BalanceLock<Balance, BlockNumber> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.amount.encode_to(dest);
+        self.until.encode_to(dest);
+    }
+}
+
+impl<Balance: Decode, BlockNumber: Decode> Decode for BalanceLock<Balance, BlockNumber> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { amount: Balance::decode(input)?, until: BlockNumber::decode(input)? })
+    }
+}
+
+/// A proof of the free balance of an account, against the storage root of the Balances Pallet, see
+/// [`Pallet::prove_balance`] and [`verify_balance_proof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceProof<Balance> {
+    /// The reserved balance of the account, which is stored along with its free balance.
+    pub reserved: Balance,
+    /// The proof that the account entry is part of the storage.
+    pub proof: MerkleProof,
+}
+
+impl<Balance: Encode> Encode for BalanceProof<Balance> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.reserved.encode_to(dest);
+        self.proof.encode_to(dest);
+    }
+}
+
+impl<Balance: Decode> Decode for BalanceProof<Balance> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { reserved: Balance::decode(input)?, proof: MerkleProof::decode(input)? })
+    }
+}
+
+/// How the fees of extrinsics are computed, and where they go, see [`Pallet::fee`]. The default
+/// schedule has no fees at all.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSchedule<AccountId, Balance> {
+    /// The fee paid by every extrinsic.
+    pub base_fee: Balance,
+    /// The fee paid for every byte of the encoded extrinsic.
+    pub fee_per_byte: Balance,
+    /// The fee paid for every unit of weight consumed by the call of the extrinsic.
+    pub fee_per_weight: Balance,
+    /// The account which fees are paid to, or `None` to burn them, decreasing the total issuance.
+    pub treasury: Option<AccountId>,
+}
+
+impl<AccountId, Balance: Zero> Default for FeeSchedule<AccountId, Balance> {
+    fn default() -> Self {
+        Self {
+            base_fee: Balance::zero(),
+            fee_per_byte: Balance::zero(),
+            fee_per_weight: Balance::zero(),
+            treasury: None,
+        }
+    }
+}
+
+impl<AccountId: Encode, Balance: Encode> Encode for FeeSchedule<AccountId, Balance> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        (&self.base_fee, &self.fee_per_byte, &self.fee_per_weight).encode_to(dest);
+        self.treasury.encode_to(dest);
+    }
+}
+
+impl<AccountId: Decode, Balance: Decode> Decode for FeeSchedule<AccountId, Balance> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        let (base_fee, fee_per_byte, fee_per_weight) = Decode::decode(input)?;
+        Ok(Self { base_fee, fee_per_byte, fee_per_weight, treasury: Option::decode(input)? })
+    }
+}
+
+/// The initial state of the Balances Pallet, e.g. read from a `genesis.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    default,
+    bound(
+        serialize = "T::AccountId: Serialize, T::Balance: Serialize",
+        deserialize = "T::AccountId: Deserialize<'de>, T::Balance: Deserialize<'de>"
+    )
+)]
+pub struct GenesisConfig<T: Config> {
+    /// The initial free balance of each account.
+    pub balances: Vec<(T::AccountId, T::Balance)>,
+    /// The expected sum of the initial balances, checked when building the genesis if given.
+    pub total_issuance: Option<T::Balance>,
+    /// The fees charged for extrinsics.
+    pub fees: FeeScheduleOf<T>,
+}
+
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        Self { balances: Vec::new(), total_issuance: None, fees: FeeSchedule::default() }
+    }
+}
+
+/// The full state of the Balances Pallet at some block, see [`Pallet::snapshot`]. Entries are
+/// sorted by account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::AccountId: Serialize, T::Balance: Serialize, T::BlockNumber: Serialize",
+    deserialize = "T::AccountId: Deserialize<'de>, T::Balance: Deserialize<'de>, \
+                   T::BlockNumber: Deserialize<'de>"
+))]
+pub struct Snapshot<T: Config> {
+    pub total_issuance: T::Balance,
+    pub accounts: Vec<(T::AccountId, AccountData<T::Balance>)>,
+    /// The locks of each account, in identifier order.
+    pub locks: Vec<(T::AccountId, LocksOf<T>)>,
+    /// The allowances as `(owner, spender, amount)`.
+    pub allowances: Vec<(T::AccountId, T::AccountId, T::Balance)>,
+    pub fees: FeeScheduleOf<T>,
+}
+
+type LockOf<T> = BalanceLock<<T as Config>::Balance, <T as crate::system::Config>::BlockNumber>;
+type LocksOf<T> = Vec<(LockIdentifier, LockOf<T>)>;
+type FeeScheduleOf<T> =
+    FeeSchedule<<T as crate::system::Config>::AccountId, <T as Config>::Balance>;
 
 // State and entry point of this module
-// For a balance system, we really only need to keep track of one thing: how much balance each user has in our system.
-pub struct Pallet {
-    balances: BTreeMap<String, u128>,  // u128: largest native type. This will allow users to have ver, very large balances.
+// For a balance system, we really only need to keep track of one thing: how much balance each user
+// has in our system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pallet<T: Config> {
+    // The storage backend holding the state of this pallet, see the storage items below.
+    storage: T::Storage,
+    // Events emitted by this pallet which have not yet been collected by the runtime.
+    events: OverlayedLog<Event<T>>,
 }
 
-impl Pallet {
+impl<T: Config> Pallet<T> {
+    // A simple storage mapping from accounts to their free and reserved balances.
+    const ACCOUNTS: StorageMap<T::AccountId, AccountData<T::Balance>> =
+        StorageMap::new(b"Balances/Accounts/");
+    // The named locks on the free balance of each account.
+    const LOCKS: StorageMap<T::AccountId, BTreeMap<LockIdentifier, LockOf<T>>> =
+        StorageMap::new(b"Balances/Locks/");
+    // The amount each spender is allowed to transfer on behalf of an owner, keyed by
+    // `(owner, spender)`.
+    const ALLOWANCES: StorageMap<(T::AccountId, T::AccountId), T::Balance> =
+        StorageMap::new(b"Balances/Allowances/");
+    // The total amount of balance in existence, i.e. the sum of all balances.
+    const TOTAL_ISSUANCE: StorageValue<T::Balance> = StorageValue::new(b"Balances/TotalIssuance");
+    // The fees charged for extrinsics, set at genesis.
+    const FEES: StorageValue<FeeScheduleOf<T>> = StorageValue::new(b"Balances/Fees");
+    // The current block number, as last given to `on_initialize`. Used to expire locks. It is
+    // kept in storage so that it survives a restart of the runtime.
+    const BLOCK_NUMBER: StorageValue<T::BlockNumber> = StorageValue::new(b"Balances/BlockNumber");
+
     /// Create a new instance of the balances module
     pub fn new() -> Self {
-        Self {
-            balances: BTreeMap::new(),
+        Self::with_storage(T::Storage::default())
+    }
+
+    /// Create a new instance of the balances module, keeping its state in `storage`.
+    pub fn with_storage(storage: T::Storage) -> Self {
+        Self { storage, events: OverlayedLog::new() }
+    }
+
+    /// Get the storage backend holding the state of this pallet.
+    pub fn storage(&self) -> &T::Storage {
+        &self.storage
+    }
+
+    /// Get the root of the Merkle tree over all the storage entries of this pallet.
+    pub fn storage_root(&self) -> crate::merkle::Hash {
+        crate::merkle::storage_root(&self.storage)
+    }
+
+    /// Build a proof of the free balance of `who` against the [storage root](Self::storage_root) of
+    /// this pallet, which can be checked with [`verify_balance_proof`] without the rest of the
+    /// storage. Accounts which do not exist, i.e. have no balance, cannot be proven.
+    pub fn prove_balance(&self, who: &T::AccountId) -> Option<BalanceProof<T::Balance>> {
+        let account = Self::ACCOUNTS.get(&self.storage, who)?;
+        let proof = merkle::storage_proof(&self.storage, &Self::ACCOUNTS.storage_key(who))?;
+        Some(BalanceProof { reserved: account.reserved, proof })
+    }
+
+    /// Persist the changes made to the storage of this pallet since the last commit. Called by the
+    /// runtime at the end of every block.
+    pub fn commit_storage(&mut self) -> std::io::Result<()> {
+        self.storage.commit()
+    }
+
+    /// Called by the runtime at the start of every block, with the number of the new block.
+    /// Removes the locks which have expired.
+    pub fn on_initialize(&mut self, block_number: T::BlockNumber) {
+        Self::BLOCK_NUMBER.set(&mut self.storage, &block_number);
+        let expired: Vec<_> = Self::LOCKS
+            .iter(&self.storage)
+            .into_iter()
+            .filter(|(_, locks)| locks.values().any(|lock| lock.until < block_number))
+            .map(|(who, mut locks)| {
+                locks.retain(|_, lock| lock.until >= block_number);
+                (who, locks)
+            })
+            .collect();
+        for (who, locks) in expired {
+            if locks.is_empty() {
+                Self::LOCKS.remove(&mut self.storage, &who);
+            } else {
+                Self::LOCKS.insert(&mut self.storage, &who, &locks);
+            }
+        }
+    }
+
+    /// Set the free balance of an account `who` to some `amount`.
+    /// The total issuance is adjusted by the difference with the previous balance. An account left
+    /// without any balance is reaped.
+    pub fn set_balance(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        let old_balance = account.free;
+        account.free = amount;
+        Self::ensure_existential(&account)?;
+        let new_total_issuance = self
+            .total_issuance()
+            .checked_sub(&old_balance)
+            .and_then(|total| total.checked_add(&amount))
+            .ok_or(Error::Overflow)?;
+        self.write_account(who, account);
+        Self::TOTAL_ISSUANCE.set(&mut self.storage, &new_total_issuance);
+        Ok(())
+    }
+
+    /// Set up the initial state of this pallet from `config`, giving each account its initial
+    /// balance. Checking the config as a whole, e.g. for duplicate accounts, is left to the caller.
+    pub fn build_genesis(&mut self, config: &GenesisConfig<T>) -> Result<(), Error> {
+        for (who, amount) in &config.balances {
+            self.set_balance(who, *amount)?;
         }
+        Self::FEES.set(&mut self.storage, &config.fees);
+        Ok(())
+    }
+
+    /// Get the full state of this pallet, e.g. to dump it as JSON.
+    pub fn snapshot(&self) -> Snapshot<T> {
+        // Entries are stored in the order of their encoded keys, which is not the order of the
+        // accounts themselves.
+        let mut accounts = Self::ACCOUNTS.iter(&self.storage);
+        accounts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut locks: Vec<_> = Self::LOCKS
+            .iter(&self.storage)
+            .into_iter()
+            .map(|(who, locks)| (who, locks.into_iter().collect()))
+            .collect();
+        locks.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut allowances: Vec<_> = Self::ALLOWANCES
+            .iter(&self.storage)
+            .into_iter()
+            .map(|((owner, spender), amount)| (owner, spender, amount))
+            .collect();
+        allowances.sort_by(|(a, b, _), (c, d, _)| (a, b).cmp(&(c, d)));
+        Snapshot {
+            total_issuance: self.total_issuance(),
+            accounts,
+            locks,
+            allowances,
+            fees: self.fees(),
+        }
+    }
+
+    /// Get the total amount of balance in existence.
+    pub fn total_issuance(&self) -> T::Balance {
+        Self::TOTAL_ISSUANCE.get(&self.storage).unwrap_or(T::Balance::zero())
+    }
+
+    /// Check the invariant that the sum of all balances, free and reserved, equals the total
+    /// issuance.
+    pub fn total_issuance_is_consistent(&self) -> bool {
+        Self::ACCOUNTS
+            .iter(&self.storage)
+            .into_iter()
+            .try_fold(T::Balance::zero(), |sum, (_, account)| sum.checked_add(&account.total()?))
+            .is_some_and(|sum| sum == self.total_issuance())
+    }
+
+    /// Get the free balance of an account `who`
+    pub fn get_balance(&self, who: &T::AccountId) -> T::Balance {
+        self.account(who).free
     }
 
-    /// Set the balance of an account `who` to some `amount`.
-    pub fn set_balance(&mut self, who: &String, amount: u128) {
-        self.balances.insert(who.clone(), amount);
+    /// Get the reserved balance of an account `who`.
+    pub fn reserved_balance(&self, who: &T::AccountId) -> T::Balance {
+        self.account(who).reserved
     }
 
-    /// Get the balance of an account `who`
-    pub fn get_balance(&mut self, who: &String) -> u128 {
-        *self.balances.get(who).unwrap_or(&0)
-        // same as return *self...;
+    /// Get the current block number, as last given to [`Pallet::on_initialize`].
+    pub fn block_number(&self) -> T::BlockNumber {
+        Self::BLOCK_NUMBER.get(&self.storage).unwrap_or(T::BlockNumber::zero())
+    }
+
+    /// Get the amount of free balance of `who` which is frozen by its active locks, i.e. the
+    /// largest of them.
+    pub fn frozen_balance(&self, who: &T::AccountId) -> T::Balance {
+        Self::LOCKS
+            .get(&self.storage, who)
+            .into_iter()
+            .flat_map(|locks| locks.into_values())
+            .filter(|lock| lock.until >= self.block_number())
+            .map(|lock| lock.amount)
+            .max()
+            .unwrap_or(T::Balance::zero())
+    }
+
+    /// Get the amount of free balance `who` can spend, i.e. its free balance minus its frozen
+    /// balance.
+    pub fn spendable_balance(&self, who: &T::AccountId) -> T::Balance {
+        self.get_balance(who)
+            .checked_sub(&self.frozen_balance(who))
+            .unwrap_or(T::Balance::zero())
+    }
+
+    /// Set the lock `id` on the balance of `who`, freezing `amount` of its free balance until
+    /// block `until` included. Replaces any existing lock with the same `id`.
+    /// The lock may freeze more than the current free balance, so that incoming funds are frozen
+    /// too.
+    pub fn set_lock(
+        &mut self,
+        id: LockIdentifier,
+        who: &T::AccountId,
+        amount: T::Balance,
+        until: T::BlockNumber,
+    ) {
+        let mut locks = Self::LOCKS.get(&self.storage, who).unwrap_or_default();
+        locks.insert(id, BalanceLock { amount, until });
+        Self::LOCKS.insert(&mut self.storage, who, &locks);
+        self.deposit_event(Event::LockSet { who: who.clone(), id, amount, until });
+    }
+
+    /// Remove the lock `id` on the balance of `who`, if any.
+    pub fn remove_lock(&mut self, id: LockIdentifier, who: &T::AccountId) {
+        let Some(mut locks) = Self::LOCKS.get(&self.storage, who) else { return };
+        if locks.remove(&id).is_some() {
+            if locks.is_empty() {
+                Self::LOCKS.remove(&mut self.storage, who);
+            } else {
+                Self::LOCKS.insert(&mut self.storage, who, &locks);
+            }
+            self.deposit_event(Event::LockRemoved { who: who.clone(), id });
+        }
+    }
+
+    /// Get the free and reserved balance of an account `who`.
+    pub fn account(&self, who: &T::AccountId) -> AccountData<T::Balance> {
+        Self::ACCOUNTS
+            .get(&self.storage, who)
+            .unwrap_or(AccountData { free: T::Balance::zero(), reserved: T::Balance::zero() })
         // Note: get returns an Option object
         // Option: Some(value) | None
         // unwrap returns the value of Some(value) [may fail if it is a None]
@@ -30,72 +507,1011 @@ impl Pallet {
     }
 
     /// Transfer `amount` from one account to another.
-	/// This function verifies that `from` has at least `amount` balance to transfer,
-	/// and that no mathematical overflows occur.
-	pub fn transfer(
-		&mut self,
-		caller: String,
-		to: String,
-		amount: u128,
-	) -> Result<(), &'static str> {
-		let caller_balance = self.get_balance(&caller);
-        let to_balance = self.get_balance(&to);
+    /// This function verifies that `from` has at least `amount` spendable balance to transfer,
+    /// and that no mathematical overflows occur.
+    /// Neither account may be left with a non-zero balance below the existential deposit; a
+    /// caller transferring away its whole balance is reaped.
+    pub fn transfer(
+        &mut self,
+        caller: T::AccountId,
+        to: T::AccountId,
+        amount: T::Balance,
+    ) -> Result<(), Error> {
+        let mut caller_account = self.account(&caller);
+        if caller == to {
+            // Nothing moves, but the caller must still be able to afford the transfer.
+            let new_free =
+                caller_account.free.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+            self.ensure_can_withdraw(&caller, new_free)?;
+            self.deposit_event(Event::Transfer { from: caller, to, amount });
+            return Ok(());
+        }
+
+        let mut to_account = self.account(&to);
         // The chained `ok_or` along with `?` follows the pattern:
-        // If checked_sub returns None, we will make the function to return an Err with the message "Not enough funds."
-        // that can be displayed to the user.
-        // Otherwise, if checked_sub returns Some(value), we will assign new_from_balance directly to that value.
-        // In this case, we are writing code which completely handles the Option type in a safe and ergonomic way.
-        let new_caller_balance = caller_balance
-            .checked_sub(amount)
-            .ok_or("Not enough funds.")?;
-        let new_to_balance = to_balance
-            .checked_add(amount)
-            .ok_or("Overflow error.")?;
-        self.set_balance(&caller, new_caller_balance);
-        self.set_balance(&to, new_to_balance);
-
-		Ok(())
-	}
+        // If checked_sub returns None, we will make the function to return an Err with the error
+        // `Error::InsufficientBalance` that can be displayed to the user.
+        // Otherwise, if checked_sub returns Some(value), we will assign caller_account.free
+        // directly to that value. In this case, we are writing code which completely handles the
+        // Option type in a safe and ergonomic way.
+        caller_account.free =
+            caller_account.free.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        to_account.free = to_account.free.checked_add(&amount).ok_or(Error::Overflow)?;
+        self.ensure_can_withdraw(&caller, caller_account.free)?;
+        Self::ensure_existential(&caller_account)?;
+        Self::ensure_existential(&to_account)?;
+        // A transfer does not change the total issuance, so we write the balances directly.
+        self.write_account(&caller, caller_account);
+        self.write_account(&to, to_account);
+        self.deposit_event(Event::Transfer { from: caller, to, amount });
+
+        Ok(())
+    }
+
+    /// Transfer from `caller` to many accounts at once, each `(to, amount)` pair being a transfer.
+    /// The sum of all amounts is checked against the caller's balance up front, and either every
+    /// transfer is applied or, if any of them fails, none is.
+    pub fn transfer_many(
+        &mut self,
+        caller: T::AccountId,
+        transfers: Vec<(T::AccountId, T::Balance)>,
+    ) -> Result<(), Error> {
+        let total = transfers
+            .iter()
+            .try_fold(T::Balance::zero(), |sum, (_, amount)| sum.checked_add(amount))
+            .ok_or(Error::Overflow)?;
+        let mut caller_account = self.account(&caller);
+        caller_account.free =
+            caller_account.free.checked_sub(&total).ok_or(Error::InsufficientBalance)?;
+
+        // Apply every transfer on a working copy of the accounts involved first, so that nothing
+        // is written unless all of them succeed.
+        let mut accounts = BTreeMap::new();
+        accounts.insert(caller.clone(), caller_account);
+        for (to, amount) in &transfers {
+            let account = accounts.entry(to.clone()).or_insert_with(|| self.account(to));
+            account.free = account.free.checked_add(amount).ok_or(Error::Overflow)?;
+        }
+        self.ensure_can_withdraw(&caller, accounts[&caller].free)?;
+        for account in accounts.values() {
+            Self::ensure_existential(account)?;
+        }
+
+        for (who, account) in accounts {
+            self.write_account(&who, account);
+        }
+        for (to, amount) in transfers {
+            self.deposit_event(Event::Transfer { from: caller.clone(), to, amount });
+        }
+        Ok(())
+    }
+
+    /// Create `amount` of new balance and credit it to `who`, increasing the total issuance.
+    pub fn mint(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        self.deposit(who, amount)?;
+        self.deposit_event(Event::Minted { who: who.clone(), amount });
+        Ok(())
+    }
+
+    /// Debit `amount` from the free balance of `who` and destroy it, decreasing the total
+    /// issuance.
+    pub fn burn(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        self.withdraw(who, amount)?;
+        self.deposit_event(Event::Burned { who: who.clone(), amount });
+        Ok(())
+    }
+
+    /// Get the fees charged for extrinsics.
+    pub fn fees(&self) -> FeeScheduleOf<T> {
+        Self::FEES.get(&self.storage).unwrap_or_default()
+    }
+
+    /// The fee of an extrinsic of `len` bytes whose call consumes `weight`: the base fee, plus the
+    /// fee for its length, plus the fee for its weight.
+    pub fn fee(&self, len: usize, weight: Weight) -> Result<T::Balance, Error> {
+        let fees = self.fees();
+        let length_fee = fees.fee_per_byte.checked_mul(&T::Balance::from(len as Weight));
+        let weight_fee = fees.fee_per_weight.checked_mul(&T::Balance::from(weight));
+        length_fee
+            .zip(weight_fee)
+            .and_then(|(length_fee, weight_fee)| {
+                fees.base_fee.checked_add(&length_fee)?.checked_add(&weight_fee)
+            })
+            .ok_or(Error::Overflow)
+    }
+
+    /// Withdraw `fee` from the free balance of `who`, before dispatching their extrinsic. It is
+    /// taken out of the total issuance until it is [settled](Self::settle_fee).
+    pub fn withdraw_fee(&mut self, who: &T::AccountId, fee: T::Balance) -> Result<(), Error> {
+        if fee.is_zero() {
+            return Ok(());
+        }
+        self.withdraw(who, fee)
+    }
+
+    /// Settle the `fee` withdrawn from `who` with [`Self::withdraw_fee`], once their extrinsic has
+    /// been dispatched: `refund` of it, e.g. for weight which was not consumed, goes back to `who`,
+    /// and the rest is paid to the treasury, or burned if there is none.
+    ///
+    /// The refund is burned too if the account of `who` was reaped in the meantime, and so is the
+    /// rest if the treasury cannot receive it, e.g. because it would be below the existential
+    /// deposit.
+    pub fn settle_fee(&mut self, who: &T::AccountId, fee: T::Balance, refund: T::Balance) {
+        let refund = refund.min(fee);
+        let refunded =
+            Self::ACCOUNTS.contains_key(&self.storage, who) && self.deposit(who, refund).is_ok();
+        let amount = if refunded {
+            fee.checked_sub(&refund).expect("the refund is at most the fee; qed")
+        } else {
+            fee
+        };
+        if amount.is_zero() {
+            return;
+        }
+        let treasury =
+            self.fees().treasury.filter(|treasury| self.deposit(treasury, amount).is_ok());
+        self.deposit_event(Event::FeePaid { who: who.clone(), amount, treasury });
+    }
+
+    /// Move `amount` from the free balance of `who` to its reserved balance, so that it can no
+    /// longer be spent.
+    pub fn reserve(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        account.free = account.free.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        account.reserved = account.reserved.checked_add(&amount).ok_or(Error::Overflow)?;
+        self.ensure_can_withdraw(who, account.free)?;
+        self.write_account(who, account);
+        self.deposit_event(Event::Reserved { who: who.clone(), amount });
+        Ok(())
+    }
+
+    /// Move `amount` from the reserved balance of `who` back to its free balance.
+    pub fn unreserve(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        account.reserved =
+            account.reserved.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        account.free = account.free.checked_add(&amount).ok_or(Error::Overflow)?;
+        self.write_account(who, account);
+        self.deposit_event(Event::Unreserved { who: who.clone(), amount });
+        Ok(())
+    }
+
+    /// Debit `amount` from the reserved balance of `who` and destroy it, decreasing the total
+    /// issuance.
+    pub fn slash_reserved(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        account.reserved =
+            account.reserved.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        let new_total_issuance =
+            self.total_issuance().checked_sub(&amount).ok_or(Error::Overflow)?;
+        Self::ensure_existential(&account)?;
+        self.write_account(who, account);
+        Self::TOTAL_ISSUANCE.set(&mut self.storage, &new_total_issuance);
+        self.deposit_event(Event::Slashed { who: who.clone(), amount });
+        Ok(())
+    }
+
+    /// Move `amount` from the reserved balance of `slashed` to the balance of `beneficiary`,
+    /// crediting either its free or its reserved balance depending on `status`.
+    pub fn repatriate_reserved(
+        &mut self,
+        slashed: &T::AccountId,
+        beneficiary: &T::AccountId,
+        amount: T::Balance,
+        status: BalanceStatus,
+    ) -> Result<(), Error> {
+        if slashed == beneficiary {
+            return match status {
+                BalanceStatus::Free => self.unreserve(slashed, amount),
+                BalanceStatus::Reserved => {
+                    // Nothing moves, but the reserved balance must still cover the amount.
+                    let reserved = self.reserved_balance(slashed);
+                    reserved.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+                    Ok(())
+                },
+            };
+        }
+
+        let mut from_account = self.account(slashed);
+        let mut to_account = self.account(beneficiary);
+        from_account.reserved =
+            from_account.reserved.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        let credited = match status {
+            BalanceStatus::Free => &mut to_account.free,
+            BalanceStatus::Reserved => &mut to_account.reserved,
+        };
+        *credited = credited.checked_add(&amount).ok_or(Error::Overflow)?;
+        Self::ensure_existential(&from_account)?;
+        Self::ensure_existential(&to_account)?;
+        self.write_account(slashed, from_account);
+        self.write_account(beneficiary, to_account);
+        self.deposit_event(Event::ReserveRepatriated {
+            from: slashed.clone(),
+            to: beneficiary.clone(),
+            amount,
+            destination_status: status,
+        });
+        Ok(())
+    }
+
+    /// Allow `spender` to transfer up to `amount` on behalf of `owner`, replacing any previous
+    /// allowance.
+    pub fn approve(&mut self, owner: T::AccountId, spender: T::AccountId, amount: T::Balance) {
+        let key = (owner.clone(), spender.clone());
+        if amount.is_zero() {
+            Self::ALLOWANCES.remove(&mut self.storage, &key);
+        } else {
+            Self::ALLOWANCES.insert(&mut self.storage, &key, &amount);
+        }
+        self.deposit_event(Event::Approval { owner, spender, amount });
+    }
+
+    /// Get the amount `spender` is allowed to transfer on behalf of `owner`.
+    pub fn allowance(&self, owner: &T::AccountId, spender: &T::AccountId) -> T::Balance {
+        Self::ALLOWANCES
+            .get(&self.storage, &(owner.clone(), spender.clone()))
+            .unwrap_or(T::Balance::zero())
+    }
+
+    /// Transfer `amount` from `owner` to `to` on behalf of `spender`, consuming that much of the
+    /// allowance `owner` gave to `spender`.
+    pub fn transfer_from(
+        &mut self,
+        spender: T::AccountId,
+        owner: T::AccountId,
+        to: T::AccountId,
+        amount: T::Balance,
+    ) -> Result<(), Error> {
+        let new_allowance = self
+            .allowance(&owner, &spender)
+            .checked_sub(&amount)
+            .ok_or(Error::InsufficientAllowance)?;
+        self.transfer(owner.clone(), to, amount)?;
+        let key = (owner, spender);
+        if new_allowance.is_zero() {
+            Self::ALLOWANCES.remove(&mut self.storage, &key);
+        } else {
+            Self::ALLOWANCES.insert(&mut self.storage, &key, &new_allowance);
+        }
+        Ok(())
+    }
+
+    /// Take the events emitted by this pallet since the last call, so that the runtime can record
+    /// them in the System Pallet.
+    pub fn take_events(&mut self) -> Vec<Event<T>>
+    where
+        Event<T>: Clone,
+    {
+        self.events.take()
+    }
+
+    fn deposit_event(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+
+    /// Credit `amount` of new balance to `who`, increasing the total issuance.
+    fn deposit(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        account.free = account.free.checked_add(&amount).ok_or(Error::Overflow)?;
+        let new_total_issuance =
+            self.total_issuance().checked_add(&amount).ok_or(Error::Overflow)?;
+        Self::ensure_existential(&account)?;
+        self.write_account(who, account);
+        Self::TOTAL_ISSUANCE.set(&mut self.storage, &new_total_issuance);
+        Ok(())
+    }
+
+    /// Debit `amount` from the free balance of `who`, decreasing the total issuance.
+    fn withdraw(&mut self, who: &T::AccountId, amount: T::Balance) -> Result<(), Error> {
+        let mut account = self.account(who);
+        account.free = account.free.checked_sub(&amount).ok_or(Error::InsufficientBalance)?;
+        let new_total_issuance =
+            self.total_issuance().checked_sub(&amount).ok_or(Error::Overflow)?;
+        self.ensure_can_withdraw(who, account.free)?;
+        Self::ensure_existential(&account)?;
+        self.write_account(who, account);
+        Self::TOTAL_ISSUANCE.set(&mut self.storage, &new_total_issuance);
+        Ok(())
+    }
+
+    /// Ensure that an account may be left holding `account`: either nothing at all, in which case
+    /// it gets reaped, or at least the existential deposit in total.
+    fn ensure_existential(account: &AccountData<T::Balance>) -> Result<(), Error> {
+        let total = account.total().ok_or(Error::Overflow)?;
+        if !total.is_zero() && total < T::EXISTENTIAL_DEPOSIT {
+            return Err(Error::BelowExistentialDeposit);
+        }
+        Ok(())
+    }
+
+    /// Ensure that the locks on the balance of `who` allow its free balance to drop to
+    /// `new_free`.
+    fn ensure_can_withdraw(&self, who: &T::AccountId, new_free: T::Balance) -> Result<(), Error> {
+        if new_free < self.frozen_balance(who) {
+            return Err(Error::LiquidityRestrictions);
+        }
+        Ok(())
+    }
+
+    /// Write the balances of `who`, creating the account if it does not exist yet, and reaping it
+    /// if it has no balance left at all. The total issuance is left untouched.
+    fn write_account(&mut self, who: &T::AccountId, account: AccountData<T::Balance>) {
+        let exists = Self::ACCOUNTS.contains_key(&self.storage, who);
+        if account.free.is_zero() && account.reserved.is_zero() {
+            if exists {
+                Self::ACCOUNTS.remove(&mut self.storage, who);
+                Self::LOCKS.remove(&mut self.storage, who);
+                self.deposit_event(Event::Reaped { account: who.clone() });
+            }
+        } else {
+            Self::ACCOUNTS.insert(&mut self.storage, who, &account);
+            if !exists {
+                self.deposit_event(Event::Endowed {
+                    account: who.clone(),
+                    free_balance: account.free,
+                });
+            }
+        }
+    }
+}
+
+/// Check that `proof` shows `who` has a free balance of `amount` in the storage of the Balances
+/// Pallet whose root is `root`.
+///
+/// The proof may also continue past the storage root of the pallet, e.g. up to the state root of
+/// a block, in which case `root` is that one.
+pub fn verify_balance_proof<T: Config>(
+    root: &Hash,
+    who: &T::AccountId,
+    amount: T::Balance,
+    proof: &BalanceProof<T::Balance>,
+) -> bool {
+    let key = Pallet::<T>::ACCOUNTS.storage_key(who);
+    let account = AccountData { free: amount, reserved: proof.reserved };
+    proof.proof.verify(root, merkle::leaf_hash(&key, &account.encode()))
+}
+
+// A public enum which describes the calls we want to expose to the dispatcher.
+// We should expect that the caller of each call will be provided by the dispatcher,
+// and not included as a parameter of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call<T: Config> {
+    /// Transfer `amount` from the caller to `to`.
+    Transfer { to: T::AccountId, amount: T::Balance },
+    /// Allow `spender` to transfer up to `amount` on behalf of the caller.
+    Approve { spender: T::AccountId, amount: T::Balance },
+    /// Transfer `amount` from `owner` to `to`, spending the allowance `owner` gave the caller.
+    TransferFrom { owner: T::AccountId, to: T::AccountId, amount: T::Balance },
+    /// Transfer from the caller to every `(to, amount)` pair of `transfers`, all or nothing.
+    TransferMany { transfers: Vec<(T::AccountId, T::Balance)> },
+}
+
+/// The weights of the calls of this pallet.
+pub mod weights {
+    use crate::support::Weight;
+
+    /// Moving balance between two existing accounts.
+    pub const TRANSFER: Weight = 1_000;
+    /// Creating the account receiving a transfer. Every transfer is charged for it, and refunded
+    /// if the account already exists.
+    pub const NEW_ACCOUNT: Weight = 500;
+    /// Recording an allowance.
+    pub const APPROVE: Weight = 500;
+    /// Spending an allowance, on top of the transfer itself.
+    pub const SPEND_ALLOWANCE: Weight = 500;
+}
+
+impl<T: Config> GetDispatchInfo for Call<T> {
+    fn weight(&self) -> Weight {
+        match self {
+            Call::Transfer { .. } => weights::TRANSFER + weights::NEW_ACCOUNT,
+            Call::Approve { .. } => weights::APPROVE,
+            Call::TransferFrom { .. } =>
+                weights::SPEND_ALLOWANCE + weights::TRANSFER + weights::NEW_ACCOUNT,
+            Call::TransferMany { transfers } =>
+                (weights::TRANSFER + weights::NEW_ACCOUNT).saturating_mul(transfers.len() as Weight),
+        }
+    }
+}
+
+// Each call is encoded as the index of its variant followed by its fields.
+impl<T: Config> Encode for Call<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Call::Transfer { to, amount } => {
+                dest.push(0);
+                (to, amount).encode_to(dest);
+            },
+            Call::Approve { spender, amount } => {
+                dest.push(1);
+                (spender, amount).encode_to(dest);
+            },
+            Call::TransferFrom { owner, to, amount } => {
+                dest.push(2);
+                (owner, to, amount).encode_to(dest);
+            },
+            Call::TransferMany { transfers } => {
+                dest.push(3);
+                transfers.encode_to(dest);
+            },
+        }
+    }
+}
+
+impl<T: Config> Decode for Call<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            0 => {
+                let (to, amount) = Decode::decode(input)?;
+                Ok(Call::Transfer { to, amount })
+            },
+            1 => {
+                let (spender, amount) = Decode::decode(input)?;
+                Ok(Call::Approve { spender, amount })
+            },
+            2 => {
+                let (owner, to, amount) = Decode::decode(input)?;
+                Ok(Call::TransferFrom { owner, to, amount })
+            },
+            3 => Ok(Call::TransferMany { transfers: Vec::decode(input)? }),
+            _ => Err(CodecError("invalid call index")),
+        }
+    }
+}
+
+/// Implementation of the dispatch logic, mapping from `Call` to the appropriate underlying
+/// function we want to execute.
+impl<T: Config> crate::support::Dispatch for Pallet<T> {
+    type Caller = T::AccountId;
+    type Call = Call<T>;
+    type Error = Error;
+
+    fn dispatch(
+        &mut self,
+        caller: Self::Caller,
+        call: Self::Call,
+    ) -> DispatchResultWithPostInfo<Self::Error> {
+        // Every transfer is charged for creating its recipient, so refund that for the recipients
+        // which already exist, and for the ones which appear more than once.
+        let actual_weight = {
+            let recipients: Vec<_> = match &call {
+                Call::Transfer { to, .. } | Call::TransferFrom { to, .. } => vec![to],
+                Call::Approve { .. } => Vec::new(),
+                Call::TransferMany { transfers } => transfers.iter().map(|(to, _)| to).collect(),
+            };
+            let new_accounts: BTreeSet<_> = recipients
+                .iter()
+                .filter(|to| !Self::ACCOUNTS.contains_key(&self.storage, to))
+                .collect();
+            let refund = weights::NEW_ACCOUNT
+                .saturating_mul((recipients.len() - new_accounts.len()) as Weight);
+            call.weight().saturating_sub(refund)
+        };
+        match call {
+            Call::Transfer { to, amount } => {
+                self.transfer(caller, to, amount)?;
+            },
+            Call::Approve { spender, amount } => {
+                self.approve(caller, spender, amount);
+            },
+            Call::TransferFrom { owner, to, amount } => {
+                self.transfer_from(caller, owner, to, amount)?;
+            },
+            Call::TransferMany { transfers } => {
+                self.transfer_many(caller, transfers)?;
+            },
+        }
+        Ok(PostDispatchInfo { actual_weight: Some(actual_weight) })
+    }
+}
+
+impl<T: Config> Transactional for Pallet<T> {
+    fn start_transaction(&mut self) {
+        self.storage.start_transaction();
+        self.events.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.storage.commit_transaction();
+        self.events.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.storage.rollback_transaction();
+        self.events.rollback_transaction();
+    }
+}
+
+impl<T: Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Let’s test!
 #[cfg(test)]
 mod tests {
-	#[test]
-	fn init_balances() {
-        let mut balances = super::Pallet::new();
+    use super::{
+        AccountData, BalanceLock, BalanceStatus, Error, Event, FeeSchedule, GenesisConfig,
+        verify_balance_proof,
+    };
+    use crate::{
+        storage::with_transaction,
+        support::{Dispatch, PostDispatchInfo, Weight},
+    };
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestConfig;
+
+    impl crate::system::Config for TestConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+        type DispatchError = ();
+        type Storage = crate::storage::InMemoryStorage;
+        const BLOCK_HASH_COUNT: u32 = 250;
+        const MAX_BLOCK_WEIGHT: crate::support::Weight = 1_000_000;
+        const EXTRINSIC_BASE_WEIGHT: crate::support::Weight = 1_000;
+    }
+
+    impl super::Config for TestConfig {
+        type Balance = u128;
+        const EXISTENTIAL_DEPOSIT: u128 = 5;
+    }
+
+    /// The result of a call which succeeded and consumed `weight`.
+    fn consumed(weight: Weight) -> Result<PostDispatchInfo, Error> {
+        Ok(PostDispatchInfo { actual_weight: Some(weight) })
+    }
+
+    #[test]
+    fn init_balances() {
+        let mut balances = super::Pallet::<TestConfig>::new();
 
         assert_eq!(balances.get_balance(&"alice".to_string()), 0);
-        balances.set_balance(&"alice".to_string(), 100);
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
         assert_eq!(balances.get_balance(&"alice".to_string()), 100);
         assert_eq!(balances.get_balance(&"bob".to_string()), 0);
-	}
+    }
+
+    #[test]
+    fn transfer_balance() {
+        /* This test checks the following:
+            - That `alice` cannot transfer funds she does not have.
+            - That `alice` can successfully transfer funds to `bob`.
+            - That the balance of `alice` and `bob` is correctly updated.
+        */
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let transfer_amount = 10;
+
+        let ini_alice_balance = balances.get_balance(&"alice".to_string());
+        assert_eq!(ini_alice_balance, 0);
+
+        let mut res = balances.transfer("alice".to_string(), "bob".to_string(), transfer_amount);
+        assert_eq!(res, Err(Error::InsufficientBalance));
+
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+        let new_alice_balance = balances.get_balance(&"alice".to_string());
+        assert_eq!(new_alice_balance, 100);
+
+        res = balances.transfer("alice".to_string(), "bob".to_string(), transfer_amount);
+        assert_eq!(res, Ok(()));
+        let end_alice_balance = balances.get_balance(&"alice".to_string());
+        let end_bob_balance = balances.get_balance(&"bob".to_string());
+        assert_eq!(end_alice_balance, 90);
+        assert_eq!(end_bob_balance, 10);
+    }
 
     #[test]
-	fn transfer_balance() {
-		/* This test checks the following:
-			- That `alice` cannot transfer funds she does not have.
-			- That `alice` can successfully transfer funds to `bob`.
-			- That the balance of `alice` and `bob` is correctly updated.
-		*/
-		let mut balances = super::Pallet::new();
-		let transfer_amount = 10;
-
-		let ini_alice_balance = balances.get_balance(&"alice".to_string());
-		assert_eq!(ini_alice_balance, 0);
-		
-		let mut res = balances.transfer("alice".to_string(), "bob".to_string(), transfer_amount);
-		assert_eq!(res, Err("Not enough funds."));
-
-		balances.set_balance(&"alice".to_string(), 100);
-		let new_alice_balance = balances.get_balance(&"alice".to_string());
-		assert_eq!(new_alice_balance, 100);
-
-		res = balances.transfer("alice".to_string(), "bob".to_string(), transfer_amount);
-		assert_eq!(res, Ok(()));
-		let end_alice_balance = balances.get_balance(&"alice".to_string());
-		let end_bob_balance = balances.get_balance(&"bob".to_string());
-		assert_eq!(end_alice_balance, 90);
-		assert_eq!(end_bob_balance, 10);
-	}
+    fn dispatch_transfer() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+
+        let call = super::Call::Transfer { to: "bob".to_string(), amount: 40 };
+        assert_eq!(balances.dispatch("alice".to_string(), call), consumed(1_500));
+        assert_eq!(balances.get_balance(&"alice".to_string()), 60);
+        assert_eq!(balances.get_balance(&"bob".to_string()), 40);
+        // Bob already exists, so creating his account is refunded.
+        let call = super::Call::Transfer { to: "bob".to_string(), amount: 10 };
+        assert_eq!(balances.dispatch("alice".to_string(), call), consumed(1_000));
+
+        let call = super::Call::Transfer { to: "alice".to_string(), amount: 51 };
+        assert_eq!(balances.dispatch("bob".to_string(), call), Err(Error::InsufficientBalance));
+    }
+
+    #[test]
+    fn overflow_error() {
+        // Balances can never add up to more than the total issuance, so a transfer cannot overflow
+        // the recipient's balance; growing the supply past its maximum is what overflows.
+        let mut balances = super::Pallet::<TestConfig>::new();
+        balances.set_balance(&"alice".to_string(), 100).unwrap();
+
+        let res = balances.set_balance(&"bob".to_string(), u128::MAX);
+        assert_eq!(res, Err(Error::Overflow));
+        assert_eq!(res.unwrap_err().to_string(), "Overflow error.");
+        assert_eq!(balances.get_balance(&"alice".to_string()), 100);
+    }
+
+    #[test]
+    fn transfer_emits_events() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 10), Ok(()));
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 5), Ok(()));
+        assert_eq!(
+            balances.transfer(bob.clone(), alice.clone(), 100),
+            Err(Error::InsufficientBalance)
+        );
+        assert_eq!(
+            balances.take_events(),
+            vec![
+                Event::Endowed { account: alice.clone(), free_balance: 100 },
+                Event::Endowed { account: bob.clone(), free_balance: 10 },
+                Event::Transfer { from: alice.clone(), to: bob.clone(), amount: 10 },
+                Event::Transfer { from: alice, to: bob, amount: 5 },
+            ]
+        );
+        assert!(balances.take_events().is_empty());
+    }
+
+    #[test]
+    fn total_issuance() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        assert_eq!(balances.total_issuance(), 0);
+
+        balances.set_balance(&alice, 100).unwrap();
+        balances.set_balance(&bob, 50).unwrap();
+        balances.set_balance(&bob, 20).unwrap();
+        assert_eq!(balances.total_issuance(), 120);
+        assert!(balances.total_issuance_is_consistent());
+
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 30), Ok(()));
+        assert_eq!(balances.transfer(alice.clone(), alice.clone(), 70), Ok(()));
+        assert_eq!(balances.get_balance(&alice), 70);
+        assert_eq!(balances.total_issuance(), 120);
+
+        assert_eq!(balances.mint(&alice, 10), Ok(()));
+        assert_eq!(balances.burn(&bob, 50), Ok(()));
+        assert_eq!(balances.burn(&bob, 1), Err(Error::InsufficientBalance));
+        assert_eq!(balances.mint(&bob, u128::MAX), Err(Error::Overflow));
+        assert_eq!(balances.set_balance(&bob, u128::MAX), Err(Error::Overflow));
+        assert_eq!(balances.get_balance(&alice), 80);
+        assert_eq!(balances.get_balance(&bob), 0);
+        assert_eq!(balances.total_issuance(), 80);
+        assert!(balances.total_issuance_is_consistent());
+    }
+
+    #[test]
+    fn existential_deposit() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let charlie = "charlie".to_string();
+
+        assert_eq!(balances.set_balance(&alice, 4), Err(Error::BelowExistentialDeposit));
+        balances.set_balance(&alice, 100).unwrap();
+
+        // Neither the recipient nor the caller may end up with dust.
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 4),
+            Err(Error::BelowExistentialDeposit)
+        );
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 96),
+            Err(Error::BelowExistentialDeposit)
+        );
+        assert_eq!(balances.burn(&alice, 98), Err(Error::BelowExistentialDeposit));
+        assert_eq!(balances.mint(&charlie, 1), Err(Error::BelowExistentialDeposit));
+
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 60), Ok(()));
+        // Transferring away the whole balance reaps the caller.
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 40), Ok(()));
+        assert_eq!(balances.get_balance(&alice), 0);
+        assert_eq!(balances.get_balance(&bob), 100);
+        assert!(balances.take_events().contains(&Event::Reaped { account: alice }));
+
+        assert_eq!(balances.burn(&bob, 100), Ok(()));
+        assert!(balances.take_events().contains(&Event::Reaped { account: bob }));
+        assert_eq!(balances.total_issuance(), 0);
+        assert!(balances.total_issuance_is_consistent());
+    }
+
+    #[test]
+    fn reserved_balance() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+
+        assert_eq!(balances.reserve(&alice, 101), Err(Error::InsufficientBalance));
+        assert_eq!(balances.reserve(&alice, 60), Ok(()));
+        assert_eq!(balances.get_balance(&alice), 40);
+        assert_eq!(balances.reserved_balance(&alice), 60);
+
+        // Only the free balance can be transferred.
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 41),
+            Err(Error::InsufficientBalance)
+        );
+        // Spending the whole free balance does not reap an account with a reserved balance.
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 40), Ok(()));
+        assert_eq!(balances.account(&alice), AccountData { free: 0, reserved: 60 });
+
+        assert_eq!(balances.unreserve(&alice, 61), Err(Error::InsufficientBalance));
+        assert_eq!(balances.unreserve(&alice, 10), Ok(()));
+        assert_eq!(balances.account(&alice), AccountData { free: 10, reserved: 50 });
+
+        assert_eq!(balances.slash_reserved(&alice, 20), Ok(()));
+        assert_eq!(balances.account(&alice), AccountData { free: 10, reserved: 30 });
+        assert_eq!(balances.total_issuance(), 80);
+
+        assert_eq!(balances.repatriate_reserved(&alice, &bob, 10, BalanceStatus::Reserved), Ok(()));
+        assert_eq!(balances.repatriate_reserved(&alice, &bob, 20, BalanceStatus::Free), Ok(()));
+        assert_eq!(balances.account(&alice), AccountData { free: 10, reserved: 0 });
+        assert_eq!(balances.account(&bob), AccountData { free: 60, reserved: 10 });
+        assert_eq!(balances.total_issuance(), 80);
+        assert!(balances.total_issuance_is_consistent());
+    }
+
+    #[test]
+    fn balance_locks() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+        balances.on_initialize(1);
+
+        balances.set_lock(*b"vesting ", &alice, 50, 2);
+        balances.set_lock(*b"voting  ", &alice, 70, 3);
+        // Locks overlap, so only the largest one counts.
+        assert_eq!(balances.frozen_balance(&alice), 70);
+        assert_eq!(balances.spendable_balance(&alice), 30);
+
+        assert_eq!(
+            balances.transfer(alice.clone(), bob.clone(), 31),
+            Err(Error::LiquidityRestrictions)
+        );
+        assert_eq!(balances.reserve(&alice, 31), Err(Error::LiquidityRestrictions));
+        assert_eq!(balances.burn(&alice, 31), Err(Error::LiquidityRestrictions));
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 30), Ok(()));
+
+        balances.remove_lock(*b"voting  ", &alice);
+        assert_eq!(balances.frozen_balance(&alice), 50);
+        assert_eq!(balances.spendable_balance(&alice), 20);
+
+        // The lock is active up to and including block 2.
+        balances.on_initialize(2);
+        assert_eq!(balances.frozen_balance(&alice), 50);
+        balances.on_initialize(3);
+        assert_eq!(balances.frozen_balance(&alice), 0);
+        assert_eq!(balances.transfer(alice.clone(), bob.clone(), 70), Ok(()));
+
+        // The block number is kept in storage, so locks still expire with a pallet reopened on it.
+        let mut balances = super::Pallet::<TestConfig>::with_storage(balances.storage().clone());
+        assert_eq!(balances.block_number(), 3);
+        balances.set_lock(*b"vesting ", &bob, 50, 2);
+        assert_eq!(balances.frozen_balance(&bob), 0);
+    }
+
+    #[test]
+    fn allowances() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let charlie = "charlie".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+
+        let call =
+            super::Call::TransferFrom { owner: alice.clone(), to: charlie.clone(), amount: 10 };
+        assert_eq!(balances.dispatch(bob.clone(), call), Err(Error::InsufficientAllowance));
+
+        let call = super::Call::Approve { spender: bob.clone(), amount: 50 };
+        assert_eq!(balances.dispatch(alice.clone(), call), consumed(500));
+        assert_eq!(balances.allowance(&alice, &bob), 50);
+        assert_eq!(balances.allowance(&bob, &alice), 0);
+
+        let call =
+            super::Call::TransferFrom { owner: alice.clone(), to: charlie.clone(), amount: 30 };
+        assert_eq!(balances.dispatch(bob.clone(), call), consumed(2_000));
+        assert_eq!(balances.get_balance(&alice), 70);
+        assert_eq!(balances.get_balance(&charlie), 30);
+        assert_eq!(balances.allowance(&alice, &bob), 20);
+
+        // A failed transfer does not consume the allowance.
+        balances.set_balance(&alice, 10).unwrap();
+        assert_eq!(
+            balances.transfer_from(bob.clone(), alice.clone(), charlie.clone(), 20),
+            Err(Error::InsufficientBalance)
+        );
+        assert_eq!(balances.allowance(&alice, &bob), 20);
+        assert_eq!(
+            balances.transfer_from(bob.clone(), alice.clone(), charlie.clone(), 21),
+            Err(Error::InsufficientAllowance)
+        );
+    }
+
+    #[test]
+    fn transfer_many() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let charlie = "charlie".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+        balances.take_events();
+
+        // The total exceeds the balance, so nothing is applied.
+        let res =
+            balances.transfer_many(alice.clone(), vec![(bob.clone(), 60), (charlie.clone(), 41)]);
+        assert_eq!(res, Err(Error::InsufficientBalance));
+        // The last transfer fails, so the first one is not applied either.
+        let res =
+            balances.transfer_many(alice.clone(), vec![(bob.clone(), 60), (charlie.clone(), 1)]);
+        assert_eq!(res, Err(Error::BelowExistentialDeposit));
+        assert_eq!(balances.get_balance(&alice), 100);
+        assert_eq!(balances.get_balance(&bob), 0);
+        assert!(balances.take_events().is_empty());
+
+        let call = super::Call::TransferMany {
+            transfers: vec![(bob.clone(), 60), (charlie.clone(), 10), (bob.clone(), 5)],
+        };
+        // Bob is only created once, so one of the three new accounts is refunded.
+        assert_eq!(balances.dispatch(alice.clone(), call), consumed(4_000));
+        assert_eq!(balances.get_balance(&alice), 25);
+        assert_eq!(balances.get_balance(&bob), 65);
+        assert_eq!(balances.get_balance(&charlie), 10);
+        assert!(balances.total_issuance_is_consistent());
+        assert_eq!(
+            balances.take_events().last(),
+            Some(&Event::Transfer { from: alice, to: bob, amount: 5 })
+        );
+    }
+
+    #[test]
+    fn transactional_storage() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+        balances.take_events();
+
+        // The second transfer fails, so the first one is rolled back too.
+        let res = with_transaction(&mut balances, |balances| {
+            balances.transfer(alice.clone(), bob.clone(), 50)?;
+            balances.reserve(&alice, 60)
+        });
+        assert_eq!(res, Err(Error::InsufficientBalance));
+        assert_eq!(balances.get_balance(&alice), 100);
+        assert_eq!(balances.get_balance(&bob), 0);
+        assert!(balances.take_events().is_empty());
+
+        let res = with_transaction(&mut balances, |balances| {
+            balances.transfer(alice.clone(), bob.clone(), 50)?;
+            balances.reserve(&alice, 40)
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(balances.account(&alice), AccountData { free: 10, reserved: 40 });
+        assert_eq!(balances.get_balance(&bob), 50);
+        assert!(balances.total_issuance_is_consistent());
+    }
+
+    #[test]
+    fn balance_proofs() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        balances.set_balance(&alice, 100).unwrap();
+        balances.set_balance(&bob, 50).unwrap();
+        balances.reserve(&bob, 20).unwrap();
+        let root = balances.storage_root();
+
+        let proof = balances.prove_balance(&bob).unwrap();
+        assert_eq!(proof.reserved, 20);
+        assert!(verify_balance_proof::<TestConfig>(&root, &bob, 30, &proof));
+        assert!(!verify_balance_proof::<TestConfig>(&root, &bob, 50, &proof));
+        assert!(!verify_balance_proof::<TestConfig>(&root, &alice, 30, &proof));
+        assert_eq!(balances.prove_balance(&"charlie".to_string()), None);
+
+        let proof = balances.prove_balance(&alice).unwrap();
+        assert!(verify_balance_proof::<TestConfig>(&root, &alice, 100, &proof));
+        balances.transfer(alice.clone(), bob, 10).unwrap();
+        assert!(!verify_balance_proof::<TestConfig>(&balances.storage_root(), &alice, 100, &proof));
+    }
+
+    #[test]
+    fn genesis_and_snapshot() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let genesis = GenesisConfig {
+            balances: vec![(bob.clone(), 50), (alice.clone(), 100)],
+            ..Default::default()
+        };
+        balances.build_genesis(&genesis).unwrap();
+        balances.reserve(&bob, 20).unwrap();
+        balances.set_lock(*b"vesting ", &alice, 30, 10);
+        balances.approve(alice.clone(), bob.clone(), 5);
+
+        let snapshot = balances.snapshot();
+        assert_eq!(snapshot.total_issuance, 150);
+        assert_eq!(
+            snapshot.accounts,
+            vec![
+                (alice.clone(), AccountData { free: 100, reserved: 0 }),
+                (bob.clone(), AccountData { free: 30, reserved: 20 }),
+            ]
+        );
+        assert_eq!(
+            snapshot.locks,
+            vec![(alice.clone(), vec![(*b"vesting ", BalanceLock { amount: 30, until: 10 })])]
+        );
+        assert_eq!(snapshot.allowances, vec![(alice, bob, 5)]);
+
+        let genesis =
+            GenesisConfig { balances: vec![("charlie".to_string(), 1)], ..Default::default() };
+        assert_eq!(balances.build_genesis(&genesis), Err(Error::BelowExistentialDeposit));
+    }
+
+    #[test]
+    fn fees() {
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let treasury = "treasury".to_string();
+        let fees = FeeSchedule {
+            base_fee: 10,
+            fee_per_byte: 1,
+            fee_per_weight: 2,
+            treasury: Some(treasury.clone()),
+        };
+        let genesis =
+            GenesisConfig { balances: vec![(alice.clone(), 1_000)], fees, ..Default::default() };
+        balances.build_genesis(&genesis).unwrap();
+        assert_eq!(balances.fee(100, 200), Ok(10 + 100 + 400));
+        assert_eq!(balances.fee(1, u64::MAX), Ok(11 + 2 * u128::from(u64::MAX)));
+
+        balances.withdraw_fee(&alice, 510).unwrap();
+        assert_eq!(balances.get_balance(&alice), 490);
+        assert_eq!(balances.total_issuance(), 490);
+        balances.take_events();
+        balances.settle_fee(&alice, 510, 200);
+        assert_eq!(balances.get_balance(&alice), 690);
+        assert_eq!(balances.get_balance(&treasury), 310);
+        assert_eq!(balances.total_issuance(), 1_000);
+        assert_eq!(
+            balances.take_events().last(),
+            Some(&Event::FeePaid { who: alice.clone(), amount: 310, treasury: Some(treasury) })
+        );
+
+        assert_eq!(balances.withdraw_fee(&alice, 691), Err(Error::InsufficientBalance));
+        assert_eq!(balances.withdraw_fee(&alice, 688), Err(Error::BelowExistentialDeposit));
+        balances.set_lock(*b"vesting ", &alice, 600, 10);
+        assert_eq!(balances.withdraw_fee(&alice, 91), Err(Error::LiquidityRestrictions));
+
+        // Without a treasury, fees are burned, and so is the refund of a reaped account.
+        let mut balances = super::Pallet::<TestConfig>::new();
+        let genesis = GenesisConfig {
+            balances: vec![(alice.clone(), 1_000)],
+            fees: FeeSchedule { base_fee: 10, ..Default::default() },
+            ..Default::default()
+        };
+        balances.build_genesis(&genesis).unwrap();
+        balances.withdraw_fee(&alice, 600).unwrap();
+        balances.settle_fee(&alice, 600, 0);
+        assert_eq!(balances.get_balance(&alice), 400);
+        assert_eq!(balances.total_issuance(), 400);
+        assert_eq!(
+            balances.take_events().last(),
+            Some(&Event::FeePaid { who: alice.clone(), amount: 600, treasury: None })
+        );
+        balances.withdraw_fee(&alice, 400).unwrap();
+        balances.settle_fee(&alice, 400, 100);
+        assert_eq!(balances.get_balance(&alice), 0);
+        assert_eq!(balances.total_issuance(), 0);
+        assert!(balances.total_issuance_is_consistent());
+    }
 }
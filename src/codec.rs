@@ -0,0 +1,408 @@
+//! A compact, deterministic binary encoding, in the style of SCALE.
+//!
+//! - Fixed width integers are encoded little-endian.
+//! - Lengths, and integers wrapped in [`Compact`], use a variable length encoding where small
+//!   values take a single byte.
+//! - Vectors, strings and maps are encoded as their length followed by their items.
+//! - Structs are encoded as their fields in order, and enums as the index of their variant followed
+//!   by its fields.
+//!
+//! Every value has exactly one valid encoding, so that encodings can be hashed and compared.
+
+use std::collections::BTreeMap;
+
+/// The error returned when some bytes are not a valid encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error(pub &'static str);
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "codec error: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A type which can be encoded into bytes.
+pub trait Encode {
+    /// Append the encoding of `self` to `dest`.
+    fn encode_to(&self, dest: &mut Vec<u8>);
+
+    /// Encode `self` into a new vector of bytes.
+    fn encode(&self) -> Vec<u8> {
+        let mut dest = Vec::new();
+        self.encode_to(&mut dest);
+        dest
+    }
+}
+
+/// A type which can be decoded from bytes.
+pub trait Decode: Sized {
+    /// Decode a value from the start of `input`, advancing it past the bytes which were read.
+    fn decode(input: &mut &[u8]) -> Result<Self, Error>;
+}
+
+/// Decode a value from `input`, which must contain exactly its encoding and nothing else.
+pub fn decode_all<T: Decode>(mut input: &[u8]) -> Result<T, Error> {
+    let value = T::decode(&mut input)?;
+    if !input.is_empty() {
+        return Err(Error("trailing bytes"));
+    }
+    Ok(value)
+}
+
+/// Read exactly `len` bytes from the start of `input`.
+fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if input.len() < len {
+        return Err(Error("not enough data"));
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! impl_fixed_width {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode_to(&self, dest: &mut Vec<u8>) {
+                    dest.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+                    let bytes = read_bytes(input, core::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().expect("read the exact size; qed")))
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width!(u8, u16, u32, u64, u128);
+
+impl Encode for bool {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error("invalid bool")),
+        }
+    }
+}
+
+/// An unsigned integer using the compact encoding:
+/// - `0..2^6` is one byte, `value << 2`;
+/// - `2^6..2^14` is two bytes, `value << 2 | 0b01`;
+/// - `2^14..2^30` is four bytes, `value << 2 | 0b10`;
+/// - anything larger is `(n - 4) << 2 | 0b11` followed by the `n` significant bytes of the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Compact<T>(pub T);
+
+fn encode_compact(value: u128, dest: &mut Vec<u8>) {
+    match value {
+        0..=0x3f => dest.push((value as u8) << 2),
+        0x40..=0x3fff => dest.extend_from_slice(&(((value as u16) << 2) | 0b01).to_le_bytes()),
+        0x4000..=0x3fff_ffff =>
+            dest.extend_from_slice(&(((value as u32) << 2) | 0b10).to_le_bytes()),
+        _ => {
+            let bytes = value.to_le_bytes();
+            let len = bytes.len() - bytes.iter().rev().take_while(|b| **b == 0).count();
+            dest.push((((len - 4) as u8) << 2) | 0b11);
+            dest.extend_from_slice(&bytes[..len]);
+        },
+    }
+}
+
+fn decode_compact(input: &mut &[u8]) -> Result<u128, Error> {
+    let prefix = u8::decode(input)?;
+    let (value, min) = match prefix & 0b11 {
+        0b00 => return Ok(u128::from(prefix >> 2)),
+        0b01 => {
+            let low = read_bytes(input, 1)?[0];
+            (u128::from(u16::from_le_bytes([prefix, low]) >> 2), 0x40)
+        },
+        0b10 => {
+            let rest = read_bytes(input, 3)?;
+            let raw = u32::from_le_bytes([prefix, rest[0], rest[1], rest[2]]);
+            (u128::from(raw >> 2), 0x4000)
+        },
+        _ => {
+            let len = usize::from(prefix >> 2) + 4;
+            if len > 16 {
+                return Err(Error("compact integer out of range"));
+            }
+            let bytes = read_bytes(input, len)?;
+            if bytes[len - 1] == 0 {
+                return Err(Error("non-canonical compact integer"));
+            }
+            let mut buf = [0u8; 16];
+            buf[..len].copy_from_slice(bytes);
+            (u128::from_le_bytes(buf), 0x4000_0000)
+        },
+    };
+    if value < min {
+        return Err(Error("non-canonical compact integer"));
+    }
+    Ok(value)
+}
+
+macro_rules! impl_compact {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for Compact<$t> {
+                fn encode_to(&self, dest: &mut Vec<u8>) {
+                    encode_compact(u128::from(self.0), dest);
+                }
+            }
+
+            impl Decode for Compact<$t> {
+                fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+                    let value = decode_compact(input)?;
+                    <$t>::try_from(value).map(Compact).map_err(|_| Error("compact integer out of range"))
+                }
+            }
+        )*
+    };
+}
+
+impl_compact!(u8, u16, u32, u64, u128);
+
+/// Encode the length of a collection.
+fn encode_len(len: usize, dest: &mut Vec<u8>) {
+    Compact(len as u64).encode_to(dest);
+}
+
+/// Decode the length of a collection.
+fn decode_len(input: &mut &[u8]) -> Result<usize, Error> {
+    let Compact(len) = Compact::<u64>::decode(input)?;
+    usize::try_from(len).map_err(|_| Error("length out of range"))
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        encode_len(self.len(), dest);
+        for item in self {
+            item.encode_to(dest);
+        }
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        (**self).encode_to(dest);
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_slice().encode_to(dest);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let len = decode_len(input)?;
+        // The length may be forged, so only reserve as much memory as the input takes. Items may
+        // take less memory once decoded, e.g. vectors, in which case the items grow past it.
+        let capacity = input.len() / core::mem::size_of::<T>().max(1);
+        let mut items = Vec::with_capacity(len.min(capacity));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_bytes().encode_to(dest);
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_str().encode_to(dest);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        String::from_utf8(Vec::decode(input)?).map_err(|_| Error("invalid utf-8"))
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        // The length is part of the type, so it is not encoded.
+        for item in self {
+            item.encode_to(dest);
+        }
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let items = (0..N).map(|_| T::decode(input)).collect::<Result<Vec<_>, _>>()?;
+        Ok(items
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("decoded exactly N items; qed")))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            None => dest.push(0),
+            Some(value) => {
+                dest.push(1);
+                value.encode_to(dest);
+            },
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            _ => Err(Error("invalid option")),
+        }
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        encode_len(self.len(), dest);
+        for (key, value) in self {
+            key.encode_to(dest);
+            value.encode_to(dest);
+        }
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let len = decode_len(input)?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::decode(input)?;
+            let value = V::decode(input)?;
+            // Keys are encoded in increasing order, anything else is not a canonical encoding.
+            if map.last_key_value().is_some_and(|(last, _)| *last >= key) {
+                return Err(Error("map keys out of order"));
+            }
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_to(&self, dest: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_to(dest);)+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+                Ok(($($name::decode(input)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+impl Encode for () {
+    fn encode_to(&self, _dest: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode(_input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compact, Decode, Encode, Error, decode_all};
+    use std::collections::BTreeMap;
+
+    fn round_trip<T: Encode + Decode + PartialEq + core::fmt::Debug>(value: T) {
+        assert_eq!(decode_all::<T>(&value.encode()), Ok(value));
+    }
+
+    #[test]
+    fn compact_integers() {
+        assert_eq!(Compact(0u32).encode(), vec![0x00]);
+        assert_eq!(Compact(1u32).encode(), vec![0x04]);
+        assert_eq!(Compact(63u32).encode(), vec![0xfc]);
+        assert_eq!(Compact(64u32).encode(), vec![0x01, 0x01]);
+        assert_eq!(Compact(16383u32).encode(), vec![0xfd, 0xff]);
+        assert_eq!(Compact(16384u32).encode(), vec![0x02, 0x00, 0x01, 0x00]);
+        assert_eq!(Compact(1u64 << 30).encode(), vec![0x03, 0x00, 0x00, 0x00, 0x40]);
+        assert_eq!(Compact(u128::MAX).encode()[0], 0b11 | (12 << 2));
+
+        for value in
+            [0, 1, 63, 64, 16383, 16384, (1 << 30) - 1, 1 << 30, u64::MAX as u128, u128::MAX]
+        {
+            round_trip(Compact(value));
+        }
+        round_trip(Compact(u32::MAX));
+
+        // Values must use the shortest encoding.
+        assert_eq!(
+            decode_all::<Compact<u32>>(&[0x01, 0x00]),
+            Err(Error("non-canonical compact integer"))
+        );
+        assert_eq!(
+            decode_all::<Compact<u8>>(&Compact(256u32).encode()),
+            Err(Error("compact integer out of range"))
+        );
+    }
+
+    #[test]
+    fn collections() {
+        round_trip(vec![1u32, 2, 3]);
+        round_trip("hello".to_string());
+        round_trip([1u8; 8]);
+        round_trip(Some((1u8, 2u64, "three".to_string())));
+        round_trip(None::<u128>);
+        round_trip(BTreeMap::from([(1u8, true), (2, false)]));
+
+        assert_eq!(vec![1u8, 2].encode(), vec![0x08, 1, 2]);
+        assert_eq!(decode_all::<Vec<u8>>(&[0x08, 1]), Err(Error("not enough data")));
+        assert_eq!(decode_all::<u8>(&[1, 2]), Err(Error("trailing bytes")));
+        assert_eq!(decode_all::<bool>(&[2]), Err(Error("invalid bool")));
+        assert_eq!(
+            decode_all::<BTreeMap<u8, bool>>(&[0x08, 2, 1, 1, 0]),
+            Err(Error("map keys out of order"))
+        );
+    }
+
+    #[test]
+    fn forged_length() {
+        // A huge length in front of a short input must not reserve memory for that many items,
+        // nor for as many items as there are bytes in the input.
+        let mut input = Compact(u32::MAX).encode();
+        input.resize(input.len() + (1 << 20), 0);
+        assert_eq!(decode_all::<Vec<[u8; 1 << 16]>>(&input), Err(Error("not enough data")));
+        assert_eq!(decode_all::<Vec<u8>>(&input), Err(Error("not enough data")));
+    }
+}
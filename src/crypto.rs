@@ -0,0 +1,171 @@
+//! Ed25519 keys and signatures, used to sign extrinsics so that only the owner of an account can
+//! make calls on its behalf.
+//!
+//! Accounts are identified by their [`Public`] key, which is written as `0x` followed by 64
+//! hexadecimal digits, e.g. in a `genesis.json`.
+
+use crate::{
+    codec::{Decode, Encode, Error as CodecError},
+    merkle, support,
+};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+/// An Ed25519 public key, which identifies an account.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Public(pub [u8; 32]);
+
+/// An Ed25519 signature.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub [u8; 64]);
+
+/// An Ed25519 key pair, which signs on behalf of its [`Public`] key.
+#[derive(Clone)]
+pub struct Pair(SigningKey);
+
+impl Pair {
+    /// The key pair with the secret `seed`.
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Self(SigningKey::from_bytes(seed))
+    }
+
+    /// A well-known key pair for development and tests, whose seed is the hash of `name`, e.g.
+    /// `"alice"`. Anyone can sign with it, so it must never hold real funds.
+    pub fn dev(name: &str) -> Self {
+        Self::from_seed(&merkle::hash(name.as_bytes()))
+    }
+}
+
+impl support::Pair for Pair {
+    type Public = Public;
+    type Signature = Signature;
+
+    fn public(&self) -> Public {
+        Public(self.0.verifying_key().to_bytes())
+    }
+
+    fn sign(&self, message: &[u8]) -> Signature {
+        Signature(self.0.sign(message).to_bytes())
+    }
+
+    fn verify(signature: &Signature, message: &[u8], public: &Public) -> bool {
+        let signature = ed25519_dalek::Signature::from_bytes(&signature.0);
+        // Strict verification rejects the weak keys and malleable signatures which plain Ed25519
+        // accepts, so that an extrinsic has a single valid signature.
+        VerifyingKey::from_bytes(&public.0)
+            .is_ok_and(|key| key.verify_strict(message, &signature).is_ok())
+    }
+}
+
+/// Write `bytes` as `0x` followed by their hexadecimal digits.
+fn fmt_hex(bytes: &[u8], f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.write_str("0x")?;
+    bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+impl core::fmt::Display for Public {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+impl core::fmt::Debug for Public {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+impl core::fmt::Debug for Signature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+impl core::str::FromStr for Public {
+    type Err = &'static str;
+
+    /// Parse a public key written as `0x` followed by 64 hexadecimal digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERROR: &str = "expected 0x followed by 64 hexadecimal digits";
+        let digits = s.strip_prefix("0x").filter(|digits| digits.len() == 64).ok_or(ERROR)?;
+        let digits = digits.chars().map(|c| c.to_digit(16).ok_or(ERROR));
+        let digits = digits.collect::<Result<Vec<_>, _>>()?;
+        let mut public = [0; 32];
+        for (byte, pair) in public.iter_mut().zip(digits.chunks(2)) {
+            *byte = (pair[0] * 16 + pair[1]) as u8;
+        }
+        Ok(Public(public))
+    }
+}
+
+impl Serialize for Public {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Public {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Encode for Public {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest);
+    }
+}
+
+impl Decode for Public {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Public(Decode::decode(input)?))
+    }
+}
+
+impl Encode for Signature {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest);
+    }
+}
+
+impl Decode for Signature {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Signature(Decode::decode(input)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pair, Public};
+    use crate::support::Pair as _;
+
+    #[test]
+    fn sign_and_verify() {
+        let alice = Pair::dev("alice");
+        let bob = Pair::dev("bob");
+        assert_ne!(alice.public(), bob.public());
+        assert_eq!(alice.public(), Pair::dev("alice").public());
+
+        let signature = alice.sign(b"hello");
+        assert!(Pair::verify(&signature, b"hello", &alice.public()));
+        assert!(!Pair::verify(&signature, b"hello!", &alice.public()));
+        assert!(!Pair::verify(&signature, b"hello", &bob.public()));
+
+        let mut tampered = signature;
+        tampered.0[0] ^= 1;
+        assert!(!Pair::verify(&tampered, b"hello", &alice.public()));
+    }
+
+    #[test]
+    fn public_to_string() {
+        let public = Public([0xab; 32]);
+        let string = public.to_string();
+        assert_eq!(string, format!("0x{}", "ab".repeat(32)));
+        assert_eq!(string.parse(), Ok(public));
+        assert_eq!(serde_json::to_string(&public).unwrap(), format!("\"{string}\""));
+
+        assert!("ab".repeat(32).parse::<Public>().is_err());
+        assert!(format!("0x{}", "ab".repeat(31)).parse::<Public>().is_err());
+        assert!(format!("0x{}", "xy".repeat(32)).parse::<Public>().is_err());
+    }
+}
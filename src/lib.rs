@@ -0,0 +1,12 @@
+//! A simple blockchain state machine, built out of independent pallets which are composed together
+//! by the [`runtime::Runtime`].
+
+pub mod balances;
+pub mod codec;
+pub mod crypto;
+pub mod merkle;
+pub mod proof_of_existence;
+pub mod runtime;
+pub mod storage;
+pub mod support;
+pub mod system;
@@ -1,7 +1,85 @@
-// Import balances module in src/balances.rs
-mod balances;
-mod system;
+use dotcodeschool_rust_state_machine::{
+    balances,
+    crypto::Pair,
+    proof_of_existence,
+    runtime::{Runtime, RuntimeCall, RuntimeEvent, RuntimeGenesisConfig},
+    support::{self, Pair as _},
+    system,
+};
 
 fn main() {
-    println!("Hello, world!");
+    // The state is persisted in the data directory given as first argument, if any, and kept in
+    // memory otherwise.
+    let mut runtime = match std::env::args().nth(1) {
+        Some(dir) => Runtime::open(dir).expect("failed to open the data directory"),
+        None => Runtime::new(),
+    };
+    // Well-known development keys; `genesis.json` endows the account of alice, and pays fees to
+    // the account of the "treasury" key.
+    let alice = Pair::dev("alice");
+    let bob = Pair::dev("bob");
+    let charlie = Pair::dev("charlie");
+
+    // Genesis state, unless it was recovered from the data directory.
+    if runtime.system().block_number() == 0 {
+        let genesis = RuntimeGenesisConfig::from_json(include_str!("../genesis.json"))
+            .expect("failed to parse the genesis config");
+        runtime.build_genesis(&genesis).expect("invalid genesis config");
+    }
+
+    // The calls of each block, with the key pair and nonce to sign them with.
+    let block_1 = vec![
+        (
+            &alice,
+            0,
+            RuntimeCall::Balances(balances::Call::Transfer { to: bob.public(), amount: 30_000 }),
+        ),
+        (
+            &alice,
+            1,
+            RuntimeCall::Balances(balances::Call::Transfer {
+                to: charlie.public(),
+                amount: 20_000,
+            }),
+        ),
+    ];
+
+    let block_2 = vec![
+        (
+            &alice,
+            2,
+            RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim {
+                claim: b"Hello, world!".to_vec(),
+            }),
+        ),
+        (
+            &bob,
+            0,
+            RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim {
+                claim: b"Hello, world!".to_vec(),
+            }),
+        ),
+    ];
+
+    // Author the blocks which were not already authored before a restart, signing their
+    // extrinsics on top of the current head.
+    let authored = runtime.system().block_number() as usize;
+    for calls in [block_1, block_2].into_iter().skip(authored) {
+        let checkpoint = runtime.checkpoint();
+        let extrinsics = calls
+            .into_iter()
+            .map(|(pair, nonce, call)| {
+                support::Extrinsic::new_signed(pair, nonce, checkpoint, call)
+            })
+            .collect();
+        let block = runtime.author_block(extrinsics).expect("invalid block");
+        println!("Authored block {} with hash {:02x?}", block.header.number, block.header.hash());
+        for event in runtime.system().events() {
+            if let RuntimeEvent::System(system::Event::ExtrinsicFailed { index, error }) = event {
+                println!("\tExtrinsic {index} failed: {error}");
+            }
+        }
+    }
+
+    println!("{}", runtime.snapshot().to_json());
 }
@@ -0,0 +1,281 @@
+//! A binary Merkle tree over the entries of a [`Storage`], used to commit to the state of the
+//! pallets with a single hash.
+//!
+//! The leaves are the hashes of the `(key, value)` entries, in key order. Each node is the hash of
+//! its two children; a node without a sibling is carried up to the next level unchanged. Leaves
+//! and nodes are hashed with a different prefix, so that a node can never be mistaken for a leaf.
+//! Trees over other data, e.g. the extrinsics of a block, hash their leaves with
+//! [`data_leaf_hash`] for the same reason.
+//!
+//! A [`MerkleProof`] shows that a leaf is part of a tree knowing only its root, with one hash per
+//! level of the tree.
+
+use crate::{
+    codec::{Decode, Encode, Error as CodecError},
+    storage::Storage,
+};
+use sha2::{Digest, Sha256};
+
+/// A 256 bit hash.
+pub type Hash = [u8; 32];
+
+/// The root of a tree without any leaf.
+pub const EMPTY_ROOT: Hash = [0; 32];
+
+/// Hash `data` with SHA-256.
+pub fn hash(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+/// The hash of the leaf holding `data`, prefixed so that it differs from the hash of any node.
+pub fn data_leaf_hash(data: &[u8]) -> Hash {
+    let mut prefixed = Vec::with_capacity(data.len() + 1);
+    prefixed.push(0);
+    prefixed.extend_from_slice(data);
+    hash(&prefixed)
+}
+
+/// The hash of the leaf for the storage entry `(key, value)`.
+pub fn leaf_hash(key: &[u8], value: &[u8]) -> Hash {
+    data_leaf_hash(&(key, value).encode())
+}
+
+/// The hash of the node whose children are `left` and `right`.
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(65);
+    data.push(1);
+    data.extend_from_slice(left);
+    data.extend_from_slice(right);
+    hash(&data)
+}
+
+/// The root of the tree with the given `leaves`.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return EMPTY_ROOT;
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!("chunks of two; qed"),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// The root of the tree over all the entries of `storage`.
+pub fn storage_root(storage: &impl Storage) -> Hash {
+    merkle_root(&storage_leaves(storage))
+}
+
+/// The leaves of the tree over all the entries of `storage`.
+fn storage_leaves(storage: &impl Storage) -> Vec<Hash> {
+    storage
+        .iter_prefix(&[])
+        .iter()
+        .map(|(key, value)| leaf_hash(key, value))
+        .collect()
+}
+
+/// One step of a [`MerkleProof`], from a node to its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofStep {
+    /// The hash of the sibling of the node.
+    pub sibling: Hash,
+    /// Whether the sibling is the left child of the parent, i.e. the node is the right one.
+    pub sibling_is_left: bool,
+}
+
+/// A proof that a leaf is part of a tree: the steps from the leaf up to the root. Levels at which
+/// the node has no sibling have no step.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// The root of the tree which this proof shows contains `leaf`.
+    pub fn root(&self, leaf: Hash) -> Hash {
+        self.steps.iter().fold(leaf, |node, step| {
+            if step.sibling_is_left {
+                node_hash(&step.sibling, &node)
+            } else {
+                node_hash(&node, &step.sibling)
+            }
+        })
+    }
+
+    /// Whether this proves that `leaf` is part of the tree with the given `root`.
+    pub fn verify(&self, root: &Hash, leaf: Hash) -> bool {
+        self.root(leaf) == *root
+    }
+}
+
+impl Encode for ProofStep {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.sibling.encode_to(dest);
+        self.sibling_is_left.encode_to(dest);
+    }
+}
+
+impl Decode for ProofStep {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { sibling: Hash::decode(input)?, sibling_is_left: bool::decode(input)? })
+    }
+}
+
+impl Encode for MerkleProof {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.steps.encode_to(dest);
+    }
+}
+
+impl Decode for MerkleProof {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { steps: Vec::decode(input)? })
+    }
+}
+
+/// Build the proof that the leaf at `index` is part of the tree with the given `leaves`, if there
+/// is such a leaf.
+pub fn merkle_proof(leaves: &[Hash], mut index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut proof = MerkleProof::default();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            proof.steps.push(ProofStep { sibling: *hash, sibling_is_left: sibling < index });
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!("chunks of two; qed"),
+            })
+            .collect();
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Build the proof that the entry at `key` is part of the tree over all the entries of `storage`,
+/// if there is such an entry.
+pub fn storage_proof(storage: &impl Storage, key: &[u8]) -> Option<MerkleProof> {
+    let entries = storage.iter_prefix(&[]);
+    let index = entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)).ok()?;
+    merkle_proof(&storage_leaves(storage), index)
+}
+
+/// Serialize a [`Hash`] as `0x` followed by 64 hexadecimal digits, the same way as a
+/// [`Public`](crate::crypto::Public) key, rather than as an array of numbers, e.g. with
+/// `#[serde(with = "merkle::hex")]`.
+pub mod hex {
+    use super::Hash;
+    use crate::crypto::Public;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// A hash serialized with [`self`], to serialize hashes nested in other types.
+    #[derive(Serialize, Deserialize)]
+    struct Hex(#[serde(with = "super::hex")] Hash);
+
+    pub fn serialize<S: Serializer>(hash: &Hash, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Public(*hash))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hash, D::Error> {
+        Public::deserialize(deserializer).map(|public| public.0)
+    }
+
+    /// Serialize a list of `(key, hash)` pairs, e.g. block numbers and their hashes, with the
+    /// hashes in hexadecimal.
+    pub mod pairs {
+        use super::{Hash, Hex};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer, K: Serialize>(
+            pairs: &[(K, Hash)],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(pairs.iter().map(|(key, hash)| (key, Hex(*hash))))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, K: Deserialize<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<(K, Hash)>, D::Error> {
+            let pairs = Vec::<(K, Hex)>::deserialize(deserializer)?;
+            Ok(pairs.into_iter().map(|(key, Hex(hash))| (key, hash)).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        EMPTY_ROOT, MerkleProof, data_leaf_hash, hash, leaf_hash, merkle_proof, merkle_root,
+        node_hash, storage_proof, storage_root,
+    };
+    use crate::{
+        codec::{Encode, decode_all},
+        storage::{InMemoryStorage, Storage},
+    };
+
+    #[test]
+    fn roots() {
+        let [a, b, c] = [leaf_hash(b"a", b"1"), leaf_hash(b"b", b"2"), leaf_hash(b"c", b"3")];
+        assert_eq!(merkle_root(&[]), EMPTY_ROOT);
+        assert_eq!(merkle_root(&[a]), a);
+        assert_eq!(merkle_root(&[a, b]), node_hash(&a, &b));
+        assert_eq!(merkle_root(&[a, b, c]), node_hash(&node_hash(&a, &b), &c));
+        assert_ne!(merkle_root(&[b, a]), merkle_root(&[a, b]));
+
+        // A leaf whose data is the concatenation of two hashes is not mistaken for their node.
+        let data = [a, b].concat();
+        assert_ne!(data_leaf_hash(&data), node_hash(&a, &b));
+        assert_ne!(data_leaf_hash(&data), hash(&data));
+
+        // The root only depends on the entries, not on the order they were written in.
+        let mut storage = InMemoryStorage::new();
+        assert_eq!(storage_root(&storage), EMPTY_ROOT);
+        storage.set(b"c", b"3".to_vec());
+        storage.set(b"a", b"1".to_vec());
+        storage.set(b"b", b"2".to_vec());
+        assert_eq!(storage_root(&storage), merkle_root(&[a, b, c]));
+
+        storage.set(b"b", b"4".to_vec());
+        assert_ne!(storage_root(&storage), merkle_root(&[a, b, c]));
+    }
+
+    #[test]
+    fn proofs() {
+        let leaves: Vec<_> = (0u8..5).map(|i| leaf_hash(&[i], &[i])).collect();
+        let root = merkle_root(&leaves);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_proof(&leaves, index).unwrap();
+            assert!(proof.verify(&root, *leaf));
+            assert_eq!(decode_all::<MerkleProof>(&proof.encode()), Ok(proof.clone()));
+            // The proof does not hold for any other leaf.
+            assert!(!proof.verify(&root, leaves[(index + 1) % leaves.len()]));
+        }
+        // The last leaf has no sibling until the top of the tree.
+        assert_eq!(merkle_proof(&leaves, 4).unwrap().steps.len(), 1);
+        assert_eq!(merkle_proof(&leaves, 5), None);
+
+        let mut storage = InMemoryStorage::new();
+        storage.set(b"a", b"1".to_vec());
+        storage.set(b"b", b"2".to_vec());
+        storage.set(b"c", b"3".to_vec());
+        let proof = storage_proof(&storage, b"b").unwrap();
+        assert!(proof.verify(&storage_root(&storage), leaf_hash(b"b", b"2")));
+        assert!(!proof.verify(&storage_root(&storage), leaf_hash(b"b", b"3")));
+        assert_eq!(storage_proof(&storage, b"d"), None);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{
+    codec::{Decode, Encode, Error as CodecError},
+    storage::{OverlayedLog, Storage, StorageMap, Transactional},
+    support::{Dispatch, DispatchResultWithPostInfo, GetDispatchInfo, PostDispatchInfo, Weight},
+};
+use core::fmt::Debug;
+
+/// The configuration trait for the Proof of Existence Pallet.
+pub trait Config: crate::system::Config {
+    /// The type which represents the content that can be claimed using this pallet.
+    /// Could be the content directly as bytes, or better yet the hash of that content.
+    /// We leave that decision to the runtime developer.
+    type Content: Clone + Ord + Debug + Encode + Decode;
+}
+
+/// The errors which can be returned by the Proof of Existence Pallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The content has already been claimed by some account.
+    AlreadyClaimed,
+    /// There is no claim for the content.
+    ClaimNotFound,
+    /// The claim is owned by a different account than the caller.
+    NotClaimOwner,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            Error::AlreadyClaimed => "This content is already claimed.",
+            Error::ClaimNotFound => "Claim does not exist.",
+            Error::NotClaimOwner => "This content is owned by someone else.",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The events emitted by the Proof of Existence Pallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T: Config> {
+    /// `owner` claimed `claim`.
+    ClaimCreated { owner: T::AccountId, claim: T::Content },
+    /// `owner` revoked their claim on `claim`.
+    ClaimRevoked { owner: T::AccountId, claim: T::Content },
+}
+
+/// This is the Proof of Existence Module.
+/// It is a simple module that allows accounts to claim existence of some data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pallet<T: Config> {
+    /// The storage backend holding the state of this pallet, see the storage items below.
+    storage: T::Storage,
+    /// Events emitted by this pallet which have not yet been collected by the runtime.
+    events: OverlayedLog<Event<T>>,
+}
+
+impl<T: Config> Pallet<T> {
+    /// A simple storage map from content to the owner of that content.
+    /// Accounts can make multiple different claims, but each claim can only have one owner.
+    const CLAIMS: StorageMap<T::Content, T::AccountId> =
+        StorageMap::new(b"ProofOfExistence/Claims/");
+
+    /// Create a new instance of the Proof of Existence Module.
+    pub fn new() -> Self {
+        Self::with_storage(T::Storage::default())
+    }
+
+    /// Create a new instance of the Proof of Existence Module, keeping its state in `storage`.
+    pub fn with_storage(storage: T::Storage) -> Self {
+        Self { storage, events: OverlayedLog::new() }
+    }
+
+    /// Get the storage backend holding the state of this pallet.
+    pub fn storage(&self) -> &T::Storage {
+        &self.storage
+    }
+
+    /// Get the root of the Merkle tree over all the storage entries of this pallet.
+    pub fn storage_root(&self) -> crate::merkle::Hash {
+        crate::merkle::storage_root(&self.storage)
+    }
+
+    /// Persist the changes made to the storage of this pallet since the last commit. Called by the
+    /// runtime at the end of every block.
+    pub fn commit_storage(&mut self) -> std::io::Result<()> {
+        self.storage.commit()
+    }
+
+    /// Get the owner (if any) of a claim.
+    pub fn get_claim(&self, claim: &T::Content) -> Option<T::AccountId> {
+        Self::CLAIMS.get(&self.storage, claim)
+    }
+
+    /// Create a new claim on behalf of the `caller`.
+    /// This function will return an error if someone already has claimed that content.
+    pub fn create_claim(&mut self, caller: T::AccountId, claim: T::Content) -> Result<(), Error> {
+        if self.get_claim(&claim).is_some() {
+            return Err(Error::AlreadyClaimed);
+        }
+        Self::CLAIMS.insert(&mut self.storage, &claim, &caller);
+        self.deposit_event(Event::ClaimCreated { owner: caller, claim });
+        Ok(())
+    }
+
+    /// Revoke an existing claim on some content.
+    /// This function should only succeed if the caller is the owner of an existing claim.
+    /// It will return an error if the claim does not exist, or if the caller is not the owner.
+    pub fn revoke_claim(&mut self, caller: T::AccountId, claim: T::Content) -> Result<(), Error> {
+        let owner = self.get_claim(&claim).ok_or(Error::ClaimNotFound)?;
+        if owner != caller {
+            return Err(Error::NotClaimOwner);
+        }
+        Self::CLAIMS.remove(&mut self.storage, &claim);
+        self.deposit_event(Event::ClaimRevoked { owner: caller, claim });
+        Ok(())
+    }
+
+    /// Take the events emitted by this pallet since the last call, so that the runtime can record
+    /// them in the System Pallet.
+    pub fn take_events(&mut self) -> Vec<Event<T>>
+    where
+        Event<T>: Clone,
+    {
+        self.events.take()
+    }
+
+    fn deposit_event(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+}
+
+impl<T: Config> Transactional for Pallet<T> {
+    fn start_transaction(&mut self) {
+        self.storage.start_transaction();
+        self.events.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.storage.commit_transaction();
+        self.events.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.storage.rollback_transaction();
+        self.events.rollback_transaction();
+    }
+}
+
+impl<T: Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A public enum which describes the calls we want to expose to the dispatcher.
+// We should expect that the caller of each call will be provided by the dispatcher,
+// and not included as a parameter of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call<T: Config> {
+    /// Claim `claim` on behalf of the caller.
+    CreateClaim { claim: T::Content },
+    /// Revoke the caller's claim on `claim`.
+    RevokeClaim { claim: T::Content },
+}
+
+/// The weights of the calls of this pallet.
+pub mod weights {
+    use crate::support::Weight;
+
+    /// Checking that the content is not claimed yet, then recording the claim.
+    pub const CREATE_CLAIM: Weight = 800;
+    /// Checking the owner of the claim, then removing it.
+    pub const REVOKE_CLAIM: Weight = 800;
+    /// Encoding and hashing each byte of the content into the key of its claim, on top of either
+    /// call.
+    pub const CLAIM_BYTE: Weight = 10;
+}
+
+impl<T: Config> GetDispatchInfo for Call<T> {
+    fn weight(&self) -> Weight {
+        let (weight, claim) = match self {
+            Call::CreateClaim { claim } => (weights::CREATE_CLAIM, claim),
+            Call::RevokeClaim { claim } => (weights::REVOKE_CLAIM, claim),
+        };
+        let len = claim.encode().len() as Weight;
+        weight.saturating_add(weights::CLAIM_BYTE.saturating_mul(len))
+    }
+}
+
+// Each call is encoded as the index of its variant followed by its fields.
+impl<T: Config> Encode for Call<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            Call::CreateClaim { claim } => {
+                dest.push(0);
+                claim.encode_to(dest);
+            },
+            Call::RevokeClaim { claim } => {
+                dest.push(1);
+                claim.encode_to(dest);
+            },
+        }
+    }
+}
+
+impl<T: Config> Decode for Call<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            0 => Ok(Call::CreateClaim { claim: T::Content::decode(input)? }),
+            1 => Ok(Call::RevokeClaim { claim: T::Content::decode(input)? }),
+            _ => Err(CodecError("invalid call index")),
+        }
+    }
+}
+
+/// Implementation of the dispatch logic, mapping from `Call` to the appropriate underlying
+/// function we want to execute.
+impl<T: Config> Dispatch for Pallet<T> {
+    type Caller = T::AccountId;
+    type Call = Call<T>;
+    type Error = Error;
+
+    fn dispatch(
+        &mut self,
+        caller: Self::Caller,
+        call: Self::Call,
+    ) -> DispatchResultWithPostInfo<Self::Error> {
+        match call {
+            Call::CreateClaim { claim } => {
+                self.create_claim(caller, claim)?;
+            },
+            Call::RevokeClaim { claim } => {
+                self.revoke_claim(caller, claim)?;
+            },
+        }
+        Ok(PostDispatchInfo::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Call, Error, Event, weights};
+    use crate::support::GetDispatchInfo;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestConfig;
+
+    impl crate::system::Config for TestConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+        type DispatchError = ();
+        type Storage = crate::storage::InMemoryStorage;
+        const BLOCK_HASH_COUNT: u32 = 250;
+        const MAX_BLOCK_WEIGHT: crate::support::Weight = 1_000_000;
+        const EXTRINSIC_BASE_WEIGHT: crate::support::Weight = 1_000;
+    }
+
+    impl super::Config for TestConfig {
+        type Content = String;
+    }
+
+    #[test]
+    fn claim_weight_scales_with_content() {
+        let claim = |len| Call::<TestConfig>::CreateClaim { claim: "a".repeat(len) };
+        // The length prefix of the content is one byte up to 63 bytes.
+        assert_eq!(claim(0).weight(), weights::CREATE_CLAIM + 10);
+        assert_eq!(claim(50).weight(), weights::CREATE_CLAIM + 510);
+        let revoke = Call::<TestConfig>::RevokeClaim { claim: "a".repeat(50) };
+        assert_eq!(revoke.weight(), weights::REVOKE_CLAIM + 510);
+    }
+
+    #[test]
+    fn basic_proof_of_existence() {
+        let mut poe = super::Pallet::<TestConfig>::new();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+        let hello = "Hello, world!".to_string();
+
+        assert_eq!(poe.get_claim(&hello), None);
+        assert_eq!(poe.create_claim(alice.clone(), hello.clone()), Ok(()));
+        assert_eq!(poe.get_claim(&hello), Some(alice.clone()));
+
+        assert_eq!(poe.create_claim(bob.clone(), hello.clone()), Err(Error::AlreadyClaimed));
+        assert_eq!(poe.revoke_claim(bob.clone(), hello.clone()), Err(Error::NotClaimOwner));
+        assert_eq!(
+            poe.revoke_claim(alice.clone(), "Goodbye!".to_string()),
+            Err(Error::ClaimNotFound)
+        );
+
+        assert_eq!(poe.revoke_claim(alice, hello.clone()), Ok(()));
+        assert_eq!(poe.get_claim(&hello), None);
+        assert_eq!(poe.create_claim(bob.clone(), hello.clone()), Ok(()));
+        assert_eq!(poe.get_claim(&hello), Some(bob.clone()));
+
+        assert_eq!(
+            poe.take_events(),
+            vec![
+                Event::ClaimCreated { owner: "alice".to_string(), claim: hello.clone() },
+                Event::ClaimRevoked { owner: "alice".to_string(), claim: hello.clone() },
+                Event::ClaimCreated { owner: bob, claim: hello },
+            ]
+        );
+    }
+}
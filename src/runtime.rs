@@ -0,0 +1,1476 @@
+use crate::{
+    balances,
+    codec::{Decode, Encode, Error as CodecError},
+    crypto, merkle, proof_of_existence,
+    storage::{FileStorage, Storage, StorageValue, Transactional, with_transaction},
+    support::{self, Dispatch, GetDispatchInfo},
+    system,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fs, io, path::Path};
+
+/// These are the concrete types we will use in our simple state machine.
+/// Modules are configured for these types directly, and they satisfy all of our trait requirements.
+pub mod types {
+    use crate::support;
+
+    pub type AccountId = crate::crypto::Public;
+    pub type Balance = u128;
+    pub type BlockNumber = u32;
+    pub type Nonce = u32;
+    pub type Content = Vec<u8>;
+    pub type Signature = crate::crypto::Signature;
+    pub type Extrinsic =
+        support::Extrinsic<AccountId, Nonce, BlockNumber, Signature, super::RuntimeCall>;
+    pub type Hash = crate::merkle::Hash;
+    pub type Header = support::Header<BlockNumber, Hash>;
+    pub type Block = support::Block<Header, Extrinsic>;
+}
+
+/// These are all the calls which are exposed to the world.
+/// Note that it is just an accumulation of the calls exposed by each module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeCall {
+    Balances(balances::Call<Runtime>),
+    ProofOfExistence(proof_of_existence::Call<Runtime>),
+}
+
+// A call is encoded as the index of its pallet, the same as in `PalletError::index`, followed by
+// the call of that pallet.
+impl Encode for RuntimeCall {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            RuntimeCall::Balances(call) => {
+                dest.push(1);
+                call.encode_to(dest);
+            },
+            RuntimeCall::ProofOfExistence(call) => {
+                dest.push(2);
+                call.encode_to(dest);
+            },
+        }
+    }
+}
+
+impl Decode for RuntimeCall {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(input)? {
+            1 => Ok(RuntimeCall::Balances(Decode::decode(input)?)),
+            2 => Ok(RuntimeCall::ProofOfExistence(Decode::decode(input)?)),
+            _ => Err(CodecError("invalid pallet index")),
+        }
+    }
+}
+
+impl GetDispatchInfo for RuntimeCall {
+    fn weight(&self) -> support::Weight {
+        match self {
+            RuntimeCall::Balances(call) => call.weight(),
+            RuntimeCall::ProofOfExistence(call) => call.weight(),
+        }
+    }
+}
+
+/// These are all the events which can be emitted by the runtime.
+/// Note that it is just an accumulation of the events emitted by each module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeEvent {
+    System(system::Event<Runtime>),
+    Balances(balances::Event<Runtime>),
+    ProofOfExistence(proof_of_existence::Event<Runtime>),
+}
+
+/// The errors of each pallet in the runtime, so that callers can match on the exact failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PalletError {
+    Balances(balances::Error),
+    ProofOfExistence(proof_of_existence::Error),
+}
+
+impl PalletError {
+    /// The index of the pallet which produced this error, in the order the pallets are declared in
+    /// the [`Runtime`].
+    pub fn index(&self) -> u8 {
+        match self {
+            PalletError::Balances(_) => 1,
+            PalletError::ProofOfExistence(_) => 2,
+        }
+    }
+}
+
+impl core::fmt::Display for PalletError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PalletError::Balances(e) => write!(f, "balances: {e}"),
+            PalletError::ProofOfExistence(e) => write!(f, "proof_of_existence: {e}"),
+        }
+    }
+}
+
+/// The error returned when dispatching a [`RuntimeCall`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchError {
+    /// An error returned by the pallet at `index`.
+    Module { index: u8, error: PalletError },
+}
+
+impl core::fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DispatchError::Module { index, error } => write!(f, "module {index}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+impl From<PalletError> for DispatchError {
+    fn from(error: PalletError) -> Self {
+        DispatchError::Module { index: error.index(), error }
+    }
+}
+
+impl From<balances::Error> for DispatchError {
+    fn from(error: balances::Error) -> Self {
+        PalletError::Balances(error).into()
+    }
+}
+
+impl From<proof_of_existence::Error> for DispatchError {
+    fn from(error: proof_of_existence::Error) -> Self {
+        PalletError::ProofOfExistence(error).into()
+    }
+}
+
+/// The initial state of the runtime, e.g. read from a `genesis.json`, see
+/// [`Runtime::build_genesis`]. Missing fields default to an empty genesis.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeGenesisConfig {
+    pub system: system::GenesisConfig<Runtime>,
+    pub balances: balances::GenesisConfig<Runtime>,
+}
+
+impl RuntimeGenesisConfig {
+    /// Parse a genesis config from JSON, e.g. the content of a `genesis.json`.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+/// The full state of the System and Balances Pallets at some block, see [`Runtime::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeSnapshot {
+    /// The hash of the block after which the snapshot was taken.
+    #[serde(with = "merkle::hex")]
+    pub head_hash: types::Hash,
+    #[serde(with = "merkle::hex")]
+    pub state_root: types::Hash,
+    pub system: system::Snapshot<Runtime>,
+    pub balances: balances::Snapshot<Runtime>,
+}
+
+impl RuntimeSnapshot {
+    /// Dump this snapshot as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a snapshot has string keys only; qed")
+    }
+}
+
+/// The error returned when building the genesis state of the runtime fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenesisError {
+    /// The genesis can only be built on top of an empty state.
+    StateNotEmpty,
+    /// The account is given more than one initial balance.
+    DuplicateAccount(types::AccountId),
+    /// The initial balances do not add up to the expected total issuance.
+    TotalIssuanceMismatch { expected: types::Balance, actual: types::Balance },
+    /// An initial balance cannot be set, e.g. because it is below the existential deposit.
+    Balances(balances::Error),
+}
+
+impl core::fmt::Display for GenesisError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GenesisError::StateNotEmpty => f.write_str("The state is not empty."),
+            GenesisError::DuplicateAccount(who) => write!(f, "Duplicate account {who}."),
+            GenesisError::TotalIssuanceMismatch { expected, actual } => {
+                write!(f, "Total issuance is {actual}, expected {expected}.")
+            },
+            GenesisError::Balances(e) => write!(f, "balances: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GenesisError {}
+
+/// This is our main Runtime.
+/// It accumulates all of the different pallets we want to use, and is the single entry point for
+/// every state transition of our blockchain.
+///
+/// A clone of a runtime is an in-memory fork of it: blocks executed on the clone are not written
+/// to the data directory it was [opened](Runtime::open) from.
+///
+/// If a block cannot be committed to disk, it may be committed to the storage of some pallets
+/// only, while it is fully executed in memory. The runtime then refuses to execute any further
+/// block, and must be opened again, which recovers the state as of the last block committed to
+/// the storage of every pallet.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    system: system::Pallet<Self>,
+    balances: balances::Pallet<Self>,
+    proof_of_existence: proof_of_existence::Pallet<Self>,
+    /// Data about the chain itself rather than its state, i.e. the hash of the head block, which
+    /// is therefore not part of the state root.
+    chain: FileStorage,
+    /// Whether committing a block to disk failed, see above.
+    failed: bool,
+}
+
+/// The hash of the last executed block, i.e. the parent of the next one.
+const HEAD_HASH: StorageValue<types::Hash> = StorageValue::new(b"Chain/HeadHash");
+
+impl system::Config for Runtime {
+    type AccountId = types::AccountId;
+    type BlockNumber = types::BlockNumber;
+    type Nonce = types::Nonce;
+    type RuntimeEvent = RuntimeEvent;
+    type DispatchError = DispatchError;
+    type Storage = FileStorage;
+    const BLOCK_HASH_COUNT: types::BlockNumber = 250;
+    const MAX_BLOCK_WEIGHT: support::Weight = 1_000_000;
+    const EXTRINSIC_BASE_WEIGHT: support::Weight = 1_000;
+}
+
+impl balances::Config for Runtime {
+    type Balance = types::Balance;
+    const EXISTENTIAL_DEPOSIT: types::Balance = 1;
+}
+
+impl proof_of_existence::Config for Runtime {
+    type Content = types::Content;
+}
+
+impl Runtime {
+    /// Create a new instance of the main Runtime, by creating a new instance of each pallet.
+    pub fn new() -> Self {
+        Self {
+            system: system::Pallet::new(),
+            balances: balances::Pallet::new(),
+            proof_of_existence: proof_of_existence::Pallet::new(),
+            chain: FileStorage::default(),
+            failed: false,
+        }
+    }
+
+    /// Open the runtime persisted in the data directory `dir`, creating it if needed.
+    ///
+    /// The state of every pallet is recovered as of the last block which was fully committed to
+    /// disk.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let system_path = dir.join("system.log");
+        let balances_path = dir.join("balances.log");
+        let proof_of_existence_path = dir.join("proof_of_existence.log");
+        let chain_path = dir.join("chain.log");
+
+        // Every block is committed to each log in turn, so a crash in between leaves the last
+        // block in some of them only. Only the blocks found in all of them are replayed.
+        let commits = FileStorage::count_commits(&system_path)?
+            .min(FileStorage::count_commits(&balances_path)?)
+            .min(FileStorage::count_commits(&proof_of_existence_path)?)
+            .min(FileStorage::count_commits(&chain_path)?);
+        Ok(Self {
+            system: system::Pallet::with_storage(FileStorage::open(system_path, Some(commits))?),
+            balances: balances::Pallet::with_storage(FileStorage::open(
+                balances_path,
+                Some(commits),
+            )?),
+            proof_of_existence: proof_of_existence::Pallet::with_storage(FileStorage::open(
+                proof_of_existence_path,
+                Some(commits),
+            )?),
+            chain: FileStorage::open(chain_path, Some(commits))?,
+            failed: false,
+        })
+    }
+
+    /// Get the hash of the last executed block, i.e. the parent of the next one. Before the first
+    /// block, this is the hash of the genesis, see [`Runtime::build_genesis`], or all zeros if
+    /// there is none.
+    pub fn head_hash(&self) -> types::Hash {
+        HEAD_HASH.get(&self.chain).unwrap_or_default()
+    }
+
+    /// Get the number and hash of the last executed block, which new extrinsics are signed on top
+    /// of, see [`support::Extrinsic::checkpoint`]. They are valid in the next `BLOCK_HASH_COUNT`
+    /// blocks.
+    pub fn checkpoint(&self) -> (types::BlockNumber, types::Hash) {
+        (self.system.block_number(), self.head_hash())
+    }
+
+    /// Read-only access to the System Pallet.
+    pub fn system(&self) -> &system::Pallet<Self> {
+        &self.system
+    }
+
+    /// Read-only access to the Balances Pallet.
+    pub fn balances(&self) -> &balances::Pallet<Self> {
+        &self.balances
+    }
+
+    /// Read-only access to the Proof of Existence Pallet.
+    pub fn proof_of_existence(&self) -> &proof_of_existence::Pallet<Self> {
+        &self.proof_of_existence
+    }
+
+    /// Set up the genesis state of the runtime from `genesis`, before the first block. Like other
+    /// changes made before it, the genesis state is committed along with the first block.
+    ///
+    /// The state root of the genesis state becomes the hash of the genesis, i.e. the parent hash
+    /// of the first block, so that extrinsics signed for this chain are not valid on another one.
+    ///
+    /// Fails, without changing anything, if the state is not empty, if an account is listed more
+    /// than once, if an initial balance cannot be set, or if the initial balances do not add up to
+    /// the expected total issuance, when one is given.
+    pub fn build_genesis(&mut self, genesis: &RuntimeGenesisConfig) -> Result<(), GenesisError> {
+        if !self.system.storage().iter_prefix(&[]).is_empty() ||
+            !self.balances.storage().iter_prefix(&[]).is_empty() ||
+            !self.proof_of_existence.storage().iter_prefix(&[]).is_empty()
+        {
+            return Err(GenesisError::StateNotEmpty);
+        }
+        let mut accounts = BTreeSet::new();
+        if let Some((who, _)) =
+            genesis.balances.balances.iter().find(|(who, _)| !accounts.insert(who))
+        {
+            return Err(GenesisError::DuplicateAccount(*who));
+        }
+        with_transaction(self, |runtime| {
+            runtime.system.build_genesis(&genesis.system);
+            let res = runtime.balances.build_genesis(&genesis.balances);
+            runtime.collect_balances_events();
+            res.map_err(GenesisError::Balances)?;
+            let actual = runtime.balances.total_issuance();
+            match genesis.balances.total_issuance {
+                Some(expected) if expected != actual =>
+                    Err(GenesisError::TotalIssuanceMismatch { expected, actual }),
+                _ => Ok(()),
+            }
+        })?;
+        let genesis_hash = self.state_root();
+        HEAD_HASH.set(&mut self.chain, &genesis_hash);
+        Ok(())
+    }
+
+    /// Get the full state of the System and Balances Pallets as of the last executed block, or
+    /// including the changes made since then, e.g. to dump it with [`RuntimeSnapshot::to_json`].
+    pub fn snapshot(&self) -> RuntimeSnapshot {
+        RuntimeSnapshot {
+            head_hash: self.head_hash(),
+            state_root: self.state_root(),
+            system: self.system.snapshot(),
+            balances: self.balances.snapshot(),
+        }
+    }
+
+    /// Get the root of the Merkle tree over the state of the runtime, whose leaves are the storage
+    /// roots of the System, Balances and Proof of Existence Pallets, in that order.
+    pub fn state_root(&self) -> types::Hash {
+        merkle::merkle_root(&self.pallet_roots())
+    }
+
+    /// Build a proof of the free balance of `who` against the [state root](Self::state_root), which
+    /// can be checked with [`balances::verify_balance_proof`], e.g. by a light client which only
+    /// knows the header of the block.
+    pub fn prove_balance(
+        &self,
+        who: &types::AccountId,
+    ) -> Option<balances::BalanceProof<types::Balance>> {
+        let mut proof = self.balances.prove_balance(who)?;
+        // Continue the proof from the storage root of the Balances Pallet up to the state root.
+        let top = merkle::merkle_proof(&self.pallet_roots(), 1).expect("three leaves; qed");
+        proof.proof.steps.extend(top.steps);
+        Some(proof)
+    }
+
+    /// Author a new block: execute `extrinsics` as the next block on top of the current head, and
+    /// return that block with the resulting state root in its header, ready to be imported by
+    /// other nodes with [`Runtime::execute_block`]. Fails without any change if one of the
+    /// extrinsics would make that block invalid, e.g. because of a bad signature.
+    ///
+    /// Extrinsics are included in order as long as their weight, including
+    /// [`system::Config::EXTRINSIC_BASE_WEIGHT`], fits in what is left of the block, see
+    /// [`system::Config::MAX_BLOCK_WEIGHT`]. The first extrinsic which does not fit,
+    /// and all those after it, are left out of the block, to be submitted again in a later block;
+    /// the returned block only holds the extrinsics which were included. Fails if the first
+    /// extrinsic does not fit even in an empty block.
+    ///
+    /// Once executed, the block becomes the new head, and is committed to disk like an imported
+    /// block.
+    pub fn author_block(
+        &mut self,
+        extrinsics: Vec<types::Extrinsic>,
+    ) -> Result<types::Block, &'static str> {
+        self.ensure_not_failed()?;
+        let number = self.next_block_number()?;
+        let parent_hash = self.head_hash();
+        let extrinsics = with_transaction(self, |runtime| {
+            runtime.initialize_block(parent_hash);
+            let mut included = Vec::new();
+            for extrinsic in extrinsics {
+                if !runtime.system.fits_in_block(Self::extrinsic_weight(&extrinsic)) {
+                    if included.is_empty() {
+                        return Err("Extrinsic is heavier than the maximum block weight.");
+                    }
+                    break;
+                }
+                runtime.apply_extrinsic(included.len(), extrinsic.clone())?;
+                included.push(extrinsic);
+            }
+            Ok(included)
+        })?;
+        let extrinsics_root = support::extrinsics_root(&extrinsics);
+        let header =
+            support::Header { parent_hash, number, extrinsics_root, state_root: self.state_root() };
+        self.finalize_block(&header)?;
+        Ok(support::Block { header, extrinsics })
+    }
+
+    /// Import a block of extrinsics, executing it. Increments the block number.
+    ///
+    /// The block is rejected as a whole if its header does not carry the expected next block
+    /// number, if its parent is not the current head, or if its extrinsics do not match the
+    /// extrinsics root of its header. Otherwise every extrinsic is dispatched in order; a failing
+    /// extrinsic is recorded as a [`system::Event::ExtrinsicFailed`] event with its index in the
+    /// block, but does not prevent the rest of the block from being executed. The nonce of each
+    /// signer is incremented for every extrinsic it submits.
+    ///
+    /// Before its call is dispatched, every extrinsic pays a fee for its length and its weight,
+    /// i.e. [`system::Config::EXTRINSIC_BASE_WEIGHT`] plus the weight of its call, see
+    /// [`balances::Pallet::fee`]. The part of the fee paid for weight which the call
+    /// did not consume is refunded afterwards; a failing call consumes its full weight.
+    ///
+    /// An extrinsic which is not signed by its signer, whose checkpoint is not one of the last
+    /// `BLOCK_HASH_COUNT` blocks, whose nonce is not the current nonce of its signer, or whose
+    /// signer cannot pay its fee, cannot be dispatched at all: the block is then rejected as a
+    /// whole. So is a block whose extrinsics weigh more than
+    /// [`system::Config::MAX_BLOCK_WEIGHT`] in total: every extrinsic must fit in the weight left
+    /// by those before it, which consume the weight they actually used.
+    ///
+    /// The block is also rejected, and all of its changes rolled back, if the state root after
+    /// executing it does not match the one in its header.
+    ///
+    /// The events of the previous block are cleared, so that after execution the System Pallet
+    /// holds exactly the events emitted by this block.
+    ///
+    /// Once executed, the block becomes the new head. It is committed to disk if the runtime was
+    /// [opened](Self::open) from a data directory. Changes made since the previous block, e.g.
+    /// genesis balances, are committed along with it. If that fails, the block is executed but
+    /// the runtime must be opened again, see [`Runtime`].
+    pub fn execute_block(&mut self, block: types::Block) -> Result<(), &'static str> {
+        self.ensure_not_failed()?;
+        let support::Block { header, extrinsics } = block;
+        if header.number != self.next_block_number()? {
+            return Err("Block number does not match what is expected.");
+        }
+        if header.parent_hash != self.head_hash() {
+            return Err("Parent hash does not match the current head.");
+        }
+        if header.extrinsics_root != support::extrinsics_root(&extrinsics) {
+            return Err("Extrinsics root does not match the extrinsics of the block.");
+        }
+        with_transaction(self, |runtime| {
+            runtime.initialize_block(header.parent_hash);
+            for (i, extrinsic) in extrinsics.into_iter().enumerate() {
+                runtime.apply_extrinsic(i, extrinsic)?;
+            }
+            if runtime.state_root() != header.state_root {
+                return Err("State root does not match the state after executing the block.");
+            }
+            Ok(())
+        })?;
+        self.finalize_block(&header)
+    }
+}
+
+impl Runtime {
+    /// The storage roots of the pallets, which are the leaves of the state root.
+    fn pallet_roots(&self) -> [types::Hash; 3] {
+        [
+            self.system.storage_root(),
+            self.balances.storage_root(),
+            self.proof_of_existence.storage_root(),
+        ]
+    }
+
+    /// The number of the block to execute next.
+    fn next_block_number(&self) -> Result<types::BlockNumber, &'static str> {
+        self.system.block_number().checked_add(1).ok_or("Block number overflow.")
+    }
+
+    /// Start the next block, on top of the block `parent_hash`.
+    fn initialize_block(&mut self, parent_hash: types::Hash) {
+        self.system.inc_block_number();
+        self.system.note_parent_hash(parent_hash);
+        self.system.reset_events();
+        self.system.reset_block_weight();
+        let block_number = self.system.block_number();
+        self.balances.on_initialize(block_number);
+    }
+
+    /// The weight charged for `extrinsic` before it is dispatched: the base weight of every
+    /// extrinsic plus the weight of its call.
+    fn extrinsic_weight(extrinsic: &types::Extrinsic) -> support::Weight {
+        <Self as system::Config>::EXTRINSIC_BASE_WEIGHT.saturating_add(extrinsic.call.weight())
+    }
+
+    /// Check and dispatch the `i`th extrinsic of the current block, charging its fee and
+    /// consuming its weight. Fails if the extrinsic has an invalid checkpoint, signature or nonce,
+    /// does not fit in the block, or cannot pay its fee, which makes the whole block invalid: the
+    /// block may then be partially applied, so this must run in a transaction.
+    fn apply_extrinsic(
+        &mut self,
+        i: usize,
+        extrinsic: types::Extrinsic,
+    ) -> Result<(), &'static str> {
+        // Only the hashes of recent blocks are kept, so older extrinsics are no longer valid.
+        let checkpoint_hash = self
+            .system
+            .block_hash(extrinsic.checkpoint)
+            .ok_or("Extrinsic checkpoint is not a recent block.")?;
+        if !extrinsic.verify::<crypto::Pair>(&checkpoint_hash) {
+            return Err("Extrinsic signature is invalid.");
+        }
+        if extrinsic.nonce != self.system.get_nonce(&extrinsic.signer) {
+            return Err("Extrinsic nonce does not match the nonce of its signer.");
+        }
+        let weight = Self::extrinsic_weight(&extrinsic);
+        if !self.system.fits_in_block(weight) {
+            return Err("Extrinsic exceeds the maximum block weight.");
+        }
+        // The fee is paid up front for the full weight of the extrinsic.
+        let len = extrinsic.encode().len();
+        let fee = self
+            .balances
+            .fee(len, weight)
+            .and_then(|fee| self.balances.withdraw_fee(&extrinsic.signer, fee).map(|()| fee))
+            .map_err(|_| "Extrinsic signer cannot pay the fee.")?;
+        self.collect_balances_events();
+
+        let call_weight = extrinsic.call.weight();
+        let support::Extrinsic { signer, call, .. } = extrinsic;
+        // Every extrinsic counts towards the signer's nonce, whether or not it succeeds.
+        self.system.inc_nonce(&signer);
+        let res = self.dispatch(signer, call);
+
+        // Only the weight which the call consumed counts towards the block, and the fee for the
+        // rest is refunded.
+        let actual_weight = <Self as system::Config>::EXTRINSIC_BASE_WEIGHT.saturating_add(
+            res.as_ref().map_or(call_weight, |info| info.actual_weight(call_weight)),
+        );
+        self.system.consume_weight(actual_weight);
+        let actual_fee = self.balances.fee(len, actual_weight).unwrap_or(fee);
+        self.balances.settle_fee(&signer, fee, fee.saturating_sub(actual_fee));
+        self.collect_balances_events();
+
+        if let Err(error) = res {
+            let index = u32::try_from(i).expect("a block has less than 2^32 extrinsics; qed");
+            let event = system::Event::ExtrinsicFailed { index, error };
+            self.system.deposit_event(RuntimeEvent::System(event));
+        }
+        Ok(())
+    }
+
+    /// Fail if a block could not be committed to disk, see [`Runtime`].
+    fn ensure_not_failed(&self) -> Result<(), &'static str> {
+        if self.failed {
+            return Err("A block could not be committed to disk, the runtime must be reopened.");
+        }
+        Ok(())
+    }
+
+    /// Make the block with `header`, which was just executed, the new head and commit it.
+    fn finalize_block(&mut self, header: &types::Header) -> Result<(), &'static str> {
+        HEAD_HASH.set(&mut self.chain, &header.hash());
+        // The block may now be committed to some of the logs only, which is only recovered from
+        // by opening the runtime again.
+        self.failed = self.commit().is_err();
+        self.ensure_not_failed()
+    }
+
+    /// Commit the storage of every persisted pallet, at the end of a block.
+    fn commit(&mut self) -> io::Result<()> {
+        self.system.commit_storage()?;
+        self.balances.commit_storage()?;
+        self.proof_of_existence.commit_storage()?;
+        self.chain.commit()
+    }
+
+    /// Record the events emitted by the Balances Pallet in the System Pallet.
+    fn collect_balances_events(&mut self) {
+        for event in self.balances.take_events() {
+            self.system.deposit_event(RuntimeEvent::Balances(event));
+        }
+    }
+}
+
+impl Transactional for Runtime {
+    fn start_transaction(&mut self) {
+        self.system.start_transaction();
+        self.balances.start_transaction();
+        self.proof_of_existence.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.system.commit_transaction();
+        self.balances.commit_transaction();
+        self.proof_of_existence.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.system.rollback_transaction();
+        self.balances.rollback_transaction();
+        self.proof_of_existence.rollback_transaction();
+    }
+}
+
+impl Dispatch for Runtime {
+    type Caller = types::AccountId;
+    type Call = RuntimeCall;
+    type Error = DispatchError;
+
+    /// Dispatch a call on behalf of a caller.
+    ///
+    /// Dispatch allows us to identify which underlying module call we want to execute.
+    /// Note that we extract the `caller` from the extrinsic, and use that information
+    /// to determine who we are executing the call on behalf of.
+    ///
+    /// Every call is executed in its own transaction: if it fails, all of its changes to storage,
+    /// including the events it emitted, are rolled back.
+    fn dispatch(
+        &mut self,
+        caller: Self::Caller,
+        call: Self::Call,
+    ) -> support::DispatchResultWithPostInfo<Self::Error> {
+        with_transaction(self, |runtime| {
+            let post_info = match call {
+                RuntimeCall::Balances(call) => {
+                    let res = runtime.balances.dispatch(caller, call);
+                    runtime.collect_balances_events();
+                    res?
+                },
+                RuntimeCall::ProofOfExistence(call) => {
+                    let res = runtime.proof_of_existence.dispatch(caller, call);
+                    for event in runtime.proof_of_existence.take_events() {
+                        runtime.system.deposit_event(RuntimeEvent::ProofOfExistence(event));
+                    }
+                    res?
+                },
+            };
+            Ok(post_info)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DispatchError, GenesisError, PalletError, Runtime, RuntimeCall, RuntimeEvent,
+        RuntimeGenesisConfig, RuntimeSnapshot, types,
+    };
+    use crate::{
+        balances,
+        codec::{Decode, Encode, Error as CodecError, decode_all},
+        crypto, proof_of_existence,
+        support::{self, GetDispatchInfo, Pair as _},
+        system,
+    };
+    use proptest::prelude::*;
+
+    /// Build the block `number` with `extrinsics`, on top of the head of `runtime` and with the
+    /// state root resulting from executing them on top of its state.
+    fn block(runtime: &Runtime, number: u32, extrinsics: Vec<types::Extrinsic>) -> types::Block {
+        let mut block = runtime.clone().author_block(extrinsics).unwrap();
+        block.header.number = number;
+        block
+    }
+
+    /// The account of the development key pair `name`.
+    fn account_id(name: &str) -> types::AccountId {
+        crypto::Pair::dev(name).public()
+    }
+
+    /// The checkpoint of the genesis of a runtime created with [`Runtime::new`], on top of which
+    /// extrinsics are valid during its first `BLOCK_HASH_COUNT` blocks.
+    const GENESIS: (types::BlockNumber, types::Hash) = (0, [0; 32]);
+
+    /// Build the genesis of `runtime`, endowing the development accounts in `balances`, and return
+    /// its checkpoint, on top of which extrinsics are valid during the first `BLOCK_HASH_COUNT`
+    /// blocks.
+    fn endow(
+        runtime: &mut Runtime,
+        balances: &[(&str, u128)],
+    ) -> (types::BlockNumber, types::Hash) {
+        let mut genesis = RuntimeGenesisConfig::default();
+        genesis.balances.balances =
+            balances.iter().map(|(name, amount)| (account_id(name), *amount)).collect();
+        runtime.build_genesis(&genesis).unwrap();
+        runtime.checkpoint()
+    }
+
+    /// Sign `call` with `nonce` by the development key pair `signer`, on top of `checkpoint`.
+    fn sign(
+        checkpoint: (types::BlockNumber, types::Hash),
+        signer: &str,
+        nonce: u32,
+        call: RuntimeCall,
+    ) -> types::Extrinsic {
+        support::Extrinsic::new_signed(&crypto::Pair::dev(signer), nonce, checkpoint, call)
+    }
+
+    fn transfer(
+        checkpoint: (types::BlockNumber, types::Hash),
+        signer: &str,
+        nonce: u32,
+        to: &str,
+        amount: u128,
+    ) -> types::Extrinsic {
+        let call = RuntimeCall::Balances(balances::Call::Transfer { to: account_id(to), amount });
+        sign(checkpoint, signer, nonce, call)
+    }
+
+    #[test]
+    fn execute_block_dispatches_extrinsics() {
+        let mut runtime = super::Runtime::new();
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+
+        let res = runtime.execute_block(block(
+            &runtime,
+            1,
+            vec![
+                transfer(genesis, "alice", 0, "bob", 30),
+                // Fails, but does not abort the rest of the block.
+                transfer(genesis, "bob", 0, "charlie", 31),
+                transfer(genesis, "alice", 1, "charlie", 20),
+            ],
+        ));
+        assert_eq!(res, Ok(()));
+        assert_eq!(runtime.system().block_number(), 1);
+        assert_eq!(runtime.balances().get_balance(&account_id("alice")), 50);
+        assert_eq!(runtime.balances().get_balance(&account_id("bob")), 30);
+        assert_eq!(runtime.balances().get_balance(&account_id("charlie")), 20);
+        assert_eq!(runtime.system().get_nonce(&account_id("alice")), 2);
+        assert_eq!(runtime.system().get_nonce(&account_id("bob")), 1);
+        assert_eq!(runtime.system().get_nonce(&account_id("charlie")), 0);
+    }
+
+    #[test]
+    fn execute_block_checks_block_number() {
+        let mut runtime = super::Runtime::new();
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+
+        let res = runtime.execute_block(block(
+            &runtime,
+            2,
+            vec![transfer(genesis, "alice", 0, "bob", 30)],
+        ));
+        assert_eq!(res, Err("Block number does not match what is expected."));
+        assert_eq!(runtime.system().block_number(), 0);
+        assert_eq!(runtime.balances().get_balance(&account_id("alice")), 100);
+
+        assert_eq!(runtime.execute_block(block(&runtime, 1, vec![])), Ok(()));
+        assert_eq!(
+            runtime.execute_block(block(&runtime, 1, vec![])),
+            Err("Block number does not match what is expected.")
+        );
+        assert_eq!(runtime.system().block_number(), 1);
+    }
+
+    #[test]
+    fn execute_block_checks_state_root() {
+        let mut author = Runtime::new();
+        let mut importer = Runtime::new();
+        let genesis = endow(&mut author, &[("alice", 100)]);
+        assert_eq!(endow(&mut importer, &[("alice", 100)]), genesis);
+        assert_eq!(author.state_root(), importer.state_root());
+
+        let block = author.author_block(vec![transfer(genesis, "alice", 0, "bob", 30)]).unwrap();
+        assert_eq!(block.header.number, 1);
+        assert_eq!(block.header.state_root, author.state_root());
+
+        let mut bad_block = block.clone();
+        bad_block.header.state_root = [1; 32];
+        assert_eq!(
+            importer.execute_block(bad_block),
+            Err("State root does not match the state after executing the block.")
+        );
+        assert_eq!(importer.system().block_number(), 0);
+        assert_eq!(importer.system().get_nonce(&account_id("alice")), 0);
+        assert_eq!(importer.balances().get_balance(&account_id("alice")), 100);
+
+        assert_eq!(importer.execute_block(block), Ok(()));
+        assert_eq!(importer.state_root(), author.state_root());
+        assert_eq!(importer.balances().get_balance(&account_id("bob")), 30);
+    }
+
+    #[test]
+    fn execute_block_checks_signatures_and_nonces() {
+        let mut runtime = Runtime::new();
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+
+        // Bob cannot sign a transfer on behalf of Alice.
+        let mut forged = transfer(genesis, "bob", 0, "bob", 30);
+        forged.signer = account_id("alice");
+        assert_eq!(
+            runtime.author_block(vec![forged.clone()]),
+            Err("Extrinsic signature is invalid.")
+        );
+        let mut block = block(&runtime, 1, vec![]);
+        block.extrinsics = vec![transfer(genesis, "alice", 0, "bob", 10), forged];
+        block.header.extrinsics_root = support::extrinsics_root(&block.extrinsics);
+        assert_eq!(runtime.execute_block(block), Err("Extrinsic signature is invalid."));
+        assert_eq!(runtime.system().block_number(), 0);
+        assert_eq!(runtime.system().get_nonce(&account_id("alice")), 0);
+        assert_eq!(runtime.balances().get_balance(&account_id("alice")), 100);
+
+        // An extrinsic cannot be replayed, nor skip ahead of the nonce of its signer.
+        let extrinsic = transfer(genesis, "alice", 0, "bob", 10);
+        assert!(runtime.author_block(vec![extrinsic.clone()]).is_ok());
+        let nonce_error = Err("Extrinsic nonce does not match the nonce of its signer.");
+        assert_eq!(runtime.author_block(vec![extrinsic]).map(|_| ()), nonce_error);
+        assert_eq!(
+            runtime.author_block(vec![transfer(genesis, "alice", 2, "bob", 10)]).map(|_| ()),
+            nonce_error
+        );
+        // A different nonce invalidates the signature.
+        let mut extrinsic = transfer(genesis, "alice", 0, "bob", 10);
+        extrinsic.nonce = 1;
+        assert_eq!(runtime.author_block(vec![extrinsic]), Err("Extrinsic signature is invalid."));
+
+        assert!(runtime.author_block(vec![transfer(genesis, "alice", 1, "bob", 10)]).is_ok());
+        assert_eq!(runtime.balances().get_balance(&account_id("bob")), 20);
+        assert_eq!(runtime.system().block_number(), 2);
+    }
+
+    #[test]
+    fn blocks_are_limited_by_weight() {
+        let mut runtime = Runtime::new();
+        let genesis = endow(&mut runtime, &[("alice", 10_000)]);
+        let transfer_many = |nonce, count| {
+            let transfers = vec![(account_id("bob"), 1); count];
+            let call = RuntimeCall::Balances(balances::Call::TransferMany { transfers });
+            sign(genesis, "alice", nonce, call)
+        };
+        // Each of these weighs 601_000 with the base weight of an extrinsic, but only creates the
+        // account of Bob once.
+        let (first, second) = (transfer_many(0, 400), transfer_many(1, 400));
+        assert_eq!(first.call.weight(), 600_000);
+
+        // Even an extrinsic whose call does nothing has a weight, so a block holds a bounded
+        // number of them.
+        let base_weight = <Runtime as system::Config>::EXTRINSIC_BASE_WEIGHT;
+        let max_extrinsics = <Runtime as system::Config>::MAX_BLOCK_WEIGHT / base_weight;
+        let empty: Vec<_> =
+            (0..=max_extrinsics as u32).map(|nonce| transfer_many(nonce, 0)).collect();
+        let included = runtime.clone().author_block(empty).unwrap().extrinsics;
+        assert_eq!(included.len() as u64, max_extrinsics);
+
+        // An extrinsic heavier than a whole block can never be included.
+        assert_eq!(
+            runtime.author_block(vec![transfer_many(0, 700)]),
+            Err("Extrinsic is heavier than the maximum block weight.")
+        );
+
+        // The second extrinsic does not fit in what the first one left of the block, so it is
+        // left out of it, and must be imported as part of a later block.
+        let too_heavy = {
+            let mut block = block(&runtime, 1, vec![]);
+            block.extrinsics = vec![first.clone(), second.clone()];
+            block.header.extrinsics_root = support::extrinsics_root(&block.extrinsics);
+            block
+        };
+        let block = runtime.clone().author_block(vec![first.clone(), second.clone()]).unwrap();
+        assert_eq!(block.extrinsics, vec![first]);
+
+        let mut importer = runtime.clone();
+        assert_eq!(
+            importer.execute_block(too_heavy),
+            Err("Extrinsic exceeds the maximum block weight.")
+        );
+        assert_eq!(importer.system().block_number(), 0);
+        assert_eq!(importer.execute_block(block), Ok(()));
+        assert_eq!(importer.system().block_weight(), 401_500);
+        assert_eq!(importer.balances().get_balance(&account_id("bob")), 400);
+
+        let block = importer.author_block(vec![second.clone()]).unwrap();
+        assert_eq!(block.extrinsics, vec![second]);
+        assert_eq!(importer.system().block_weight(), 401_000);
+        assert_eq!(importer.balances().get_balance(&account_id("bob")), 800);
+    }
+
+    #[test]
+    fn extrinsics_pay_fees() {
+        let (alice, bob, treasury) =
+            (account_id("alice"), account_id("bob"), account_id("treasury"));
+        let mut genesis = RuntimeGenesisConfig::default();
+        genesis.balances.balances = vec![(alice, 1_000_000)];
+        genesis.balances.fees = balances::FeeSchedule {
+            base_fee: 100,
+            fee_per_byte: 1,
+            fee_per_weight: 2,
+            treasury: Some(treasury),
+        };
+        let mut runtime = Runtime::new();
+        runtime.build_genesis(&genesis).unwrap();
+        let genesis = runtime.checkpoint();
+        let fee = |extrinsic: &types::Extrinsic, weight: u128| {
+            100 + extrinsic.encode().len() as u128 + 2 * weight
+        };
+
+        // Bob does not exist yet, so the transfer consumes its full weight.
+        let first = transfer(genesis, "alice", 0, "bob", 10_000);
+        let first_fee = fee(&first, 2_500);
+        runtime.author_block(vec![first]).unwrap();
+        assert_eq!(runtime.balances().get_balance(&alice), 1_000_000 - 10_000 - first_fee);
+        assert_eq!(runtime.balances().get_balance(&treasury), first_fee);
+        assert_eq!(runtime.balances().total_issuance(), 1_000_000);
+        assert!(runtime.system().events().contains(&RuntimeEvent::Balances(
+            balances::Event::FeePaid { who: alice, amount: first_fee, treasury: Some(treasury) }
+        )));
+
+        // Now that Bob exists, creating his account is refunded. A failing call still pays its
+        // full weight.
+        let second = transfer(genesis, "alice", 1, "bob", 10_000);
+        let failing = transfer(genesis, "bob", 0, "alice", 100_000);
+        let failing_fee = fee(&failing, 2_500);
+        let fees = fee(&second, 2_000) + failing_fee;
+        runtime.author_block(vec![second, failing]).unwrap();
+        assert_eq!(runtime.balances().get_balance(&bob), 20_000 - failing_fee);
+        assert_eq!(runtime.balances().get_balance(&treasury), first_fee + fees);
+        assert_eq!(runtime.system().get_nonce(&bob), 1);
+
+        // An extrinsic whose signer cannot pay its fee makes the block invalid.
+        assert_eq!(
+            runtime.author_block(vec![transfer(genesis, "charlie", 0, "alice", 1)]),
+            Err("Extrinsic signer cannot pay the fee.")
+        );
+        assert_eq!(runtime.system().block_number(), 2);
+    }
+
+    #[test]
+    fn blocks_are_chained_by_parent_hash() {
+        let mut author = Runtime::new();
+        let mut importer = Runtime::new();
+        let block_1 = author.author_block(vec![]).unwrap();
+        let block_2 = author.author_block(vec![]).unwrap();
+        assert_eq!(block_1.header.parent_hash, [0; 32]);
+        assert_eq!(block_2.header.parent_hash, block_1.header.hash());
+        assert_eq!(author.head_hash(), block_2.header.hash());
+
+        // A block on top of a different parent is rejected.
+        let mut orphan = block_1.clone();
+        orphan.header.parent_hash = [1; 32];
+        assert_eq!(
+            importer.execute_block(orphan),
+            Err("Parent hash does not match the current head.")
+        );
+
+        // The header commits to the extrinsics of the block.
+        let mut tampered = block_1.clone();
+        tampered.extrinsics.push(transfer(GENESIS, "alice", 0, "bob", 10));
+        assert_eq!(
+            importer.execute_block(tampered),
+            Err("Extrinsics root does not match the extrinsics of the block.")
+        );
+
+        assert_eq!(importer.execute_block(block_1.clone()), Ok(()));
+        assert_eq!(importer.head_hash(), block_1.header.hash());
+        assert_eq!(importer.execute_block(block_2), Ok(()));
+        // The hash of each block is recorded in the state of the next one.
+        assert_eq!(importer.system().block_hash(1), Some(block_1.header.hash()));
+        assert_eq!(importer.head_hash(), author.head_hash());
+        assert_eq!(importer.state_root(), author.state_root());
+    }
+
+    #[test]
+    fn balance_proofs_against_state_root() {
+        let mut runtime = Runtime::new();
+        let alice = account_id("alice");
+        let bob = account_id("bob");
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+        let block = runtime.author_block(vec![transfer(genesis, "alice", 0, "bob", 30)]).unwrap();
+        let root = block.header.state_root;
+
+        let proof = runtime.prove_balance(&bob).unwrap();
+        assert!(balances::verify_balance_proof::<Runtime>(&root, &bob, 30, &proof));
+        assert!(!balances::verify_balance_proof::<Runtime>(&root, &bob, 31, &proof));
+        assert!(!balances::verify_balance_proof::<Runtime>(&root, &alice, 30, &proof));
+
+        let proof = runtime.prove_balance(&alice).unwrap();
+        assert!(balances::verify_balance_proof::<Runtime>(&root, &alice, 70, &proof));
+        assert_eq!(runtime.prove_balance(&account_id("charlie")), None);
+
+        // A proof does not hold against the state root of a later block.
+        let block = runtime
+            .author_block(vec![transfer(genesis, "alice", 1, "charlie", 10)])
+            .unwrap();
+        assert!(!balances::verify_balance_proof::<Runtime>(
+            &block.header.state_root,
+            &alice,
+            70,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn state_root_covers_claims() {
+        let create_claim = |claim: &str| {
+            let call = RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim {
+                claim: claim.as_bytes().to_vec(),
+            });
+            sign(GENESIS, "alice", 0, call)
+        };
+        let mut first = Runtime::new();
+        let mut second = Runtime::new();
+        // Only the claims differ after these blocks, which must be enough to change the root. They
+        // have the same length, so that the weights of the blocks do not differ.
+        let first_block = first.author_block(vec![create_claim("first")]).unwrap();
+        let second_block = second.author_block(vec![create_claim("other")]).unwrap();
+        assert_eq!(first.system().snapshot(), second.system().snapshot());
+        assert_eq!(first.balances().snapshot(), second.balances().snapshot());
+        assert_ne!(first_block.header.state_root, second_block.header.state_root);
+    }
+
+    #[test]
+    fn dispatch_errors_carry_module_index() {
+        let mut runtime = super::Runtime::new();
+
+        let transfer = transfer(GENESIS, "alice", 0, "bob", 10);
+        let res = support::Dispatch::dispatch(&mut runtime, transfer.signer, transfer.call);
+        assert_eq!(
+            res,
+            Err(DispatchError::Module {
+                index: 1,
+                error: PalletError::Balances(balances::Error::InsufficientBalance)
+            })
+        );
+    }
+
+    #[test]
+    fn execute_block_with_multiple_pallets() {
+        let mut runtime = super::Runtime::new();
+        let alice = account_id("alice");
+        let bob = account_id("bob");
+        let create_claim = |signer: &str, claim: &str| {
+            let call = RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim {
+                claim: claim.as_bytes().to_vec(),
+            });
+            sign(GENESIS, signer, 0, call)
+        };
+
+        let res = runtime.execute_block(block(
+            &runtime,
+            1,
+            vec![create_claim("alice", "Hello, world!"), create_claim("bob", "Hello, world!")],
+        ));
+        assert_eq!(res, Ok(()));
+        assert_eq!(runtime.proof_of_existence().get_claim(&b"Hello, world!".to_vec()), Some(alice));
+
+        let revoke = RuntimeCall::ProofOfExistence(proof_of_existence::Call::RevokeClaim {
+            claim: b"Hello, world!".to_vec(),
+        });
+        assert_eq!(
+            support::Dispatch::dispatch(&mut runtime, bob, revoke),
+            Err(DispatchError::Module {
+                index: 2,
+                error: PalletError::ProofOfExistence(proof_of_existence::Error::NotClaimOwner)
+            })
+        );
+    }
+
+    #[test]
+    fn events_are_recorded_per_block() {
+        let mut runtime = super::Runtime::new();
+        let alice = account_id("alice");
+        let bob = account_id("bob");
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+
+        let res = runtime.execute_block(block(
+            &runtime,
+            1,
+            vec![
+                transfer(genesis, "alice", 0, "bob", 30),
+                transfer(genesis, "bob", 0, "charlie", 31),
+            ],
+        ));
+        assert_eq!(res, Ok(()));
+        assert_eq!(
+            runtime.system().events(),
+            &[
+                RuntimeEvent::Balances(balances::Event::Endowed { account: bob, free_balance: 30 }),
+                RuntimeEvent::Balances(balances::Event::Transfer {
+                    from: alice,
+                    to: bob,
+                    amount: 30
+                }),
+                // The failing transfer emits no event of its own, only its failure is recorded.
+                RuntimeEvent::System(system::Event::ExtrinsicFailed {
+                    index: 1,
+                    error: balances::Error::InsufficientBalance.into()
+                }),
+            ]
+        );
+
+        // A rejected block leaves the events of the previous block in place.
+        let events = runtime.system().events().to_vec();
+        let mut bad_block = block(&runtime, 2, vec![transfer(genesis, "alice", 1, "bob", 10)]);
+        bad_block.header.state_root = [1; 32];
+        assert!(runtime.execute_block(bad_block).is_err());
+        assert_eq!(
+            runtime.author_block(vec![transfer(genesis, "alice", 0, "bob", 10)]).map(|_| ()),
+            Err("Extrinsic nonce does not match the nonce of its signer.")
+        );
+        assert_eq!(runtime.system().block_number(), 1);
+        assert_eq!(runtime.system().events(), events);
+
+        assert_eq!(runtime.execute_block(block(&runtime, 2, vec![])), Ok(()));
+        assert!(runtime.system().events().is_empty());
+    }
+
+    #[test]
+    fn extrinsics_cannot_be_replayed_after_reaping() {
+        let mut runtime = super::Runtime::new();
+        let alice = account_id("alice");
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+
+        let first = transfer(genesis, "alice", 0, "bob", 10);
+        assert_eq!(runtime.execute_block(block(&runtime, 1, vec![first.clone()])), Ok(()));
+        assert_eq!(runtime.system().get_nonce(&alice), 1);
+
+        assert_eq!(
+            runtime.execute_block(block(
+                &runtime,
+                2,
+                vec![transfer(genesis, "alice", 1, "bob", 90)]
+            )),
+            Ok(())
+        );
+        assert_eq!(runtime.balances().get_balance(&alice), 0);
+        assert!(
+            runtime
+                .system()
+                .events()
+                .contains(&RuntimeEvent::Balances(balances::Event::Reaped { account: alice }))
+        );
+        // The nonce outlives the account, so that its extrinsics cannot be replayed once it is
+        // funded again.
+        assert_eq!(runtime.system().get_nonce(&alice), 2);
+        assert!(runtime.author_block(vec![transfer(genesis, "bob", 0, "alice", 50)]).is_ok());
+        assert_eq!(
+            runtime.author_block(vec![first]),
+            Err("Extrinsic nonce does not match the nonce of its signer.")
+        );
+        assert_eq!(runtime.balances().get_balance(&alice), 50);
+    }
+
+    #[test]
+    fn extrinsics_expire() {
+        let mut runtime = super::Runtime::new();
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+        assert_eq!(genesis, (0, runtime.head_hash()));
+
+        // The checkpoint must be a block before the one the extrinsic is executed in.
+        let future = transfer((1, genesis.1), "alice", 0, "bob", 10);
+        let checkpoint_error = Err("Extrinsic checkpoint is not a recent block.");
+        assert_eq!(runtime.author_block(vec![future]), checkpoint_error);
+        // The hash of the checkpoint is signed, so an extrinsic for another chain is invalid.
+        let forked = transfer((0, [1; 32]), "alice", 0, "bob", 10);
+        assert_eq!(runtime.author_block(vec![forked]), Err("Extrinsic signature is invalid."));
+
+        // Extrinsics are valid in the `BLOCK_HASH_COUNT` blocks after their checkpoint.
+        for _ in 1..<Runtime as system::Config>::BLOCK_HASH_COUNT {
+            runtime.author_block(vec![]).unwrap();
+        }
+        let extrinsic = transfer(genesis, "alice", 0, "bob", 10);
+        assert!(runtime.clone().author_block(vec![extrinsic.clone()]).is_ok());
+        runtime.author_block(vec![]).unwrap();
+        assert_eq!(runtime.author_block(vec![extrinsic]), checkpoint_error);
+        let extrinsic = transfer(runtime.checkpoint(), "alice", 0, "bob", 10);
+        assert!(runtime.author_block(vec![extrinsic]).is_ok());
+    }
+
+    #[test]
+    fn state_persists_across_restarts() {
+        let dir =
+            std::env::temp_dir().join(format!("state-machine-runtime-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let alice = account_id("alice");
+        let bob = account_id("bob");
+
+        let mut runtime = super::Runtime::open(&dir).unwrap();
+        let genesis = endow(&mut runtime, &[("alice", 100)]);
+        assert_eq!(
+            runtime
+                .author_block(vec![transfer(genesis, "alice", 0, "bob", 30)])
+                .map(|block| block.header.number),
+            Ok(1)
+        );
+        let balances_len = std::fs::metadata(dir.join("balances.log")).unwrap().len();
+        // A fork of the runtime does not write to its data directory.
+        runtime
+            .clone()
+            .author_block(vec![transfer(genesis, "alice", 1, "bob", 5)])
+            .unwrap();
+        assert_eq!(std::fs::metadata(dir.join("balances.log")).unwrap().len(), balances_len);
+        let claim = b"Hello, world!".to_vec();
+        let create_claim = sign(
+            genesis,
+            "alice",
+            2,
+            RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim {
+                claim: claim.clone(),
+            }),
+        );
+        assert_eq!(
+            runtime
+                .author_block(vec![transfer(genesis, "alice", 1, "bob", 20), create_claim])
+                .map(|block| block.header.number),
+            Ok(2)
+        );
+        let head_hash = runtime.head_hash();
+        // Changes made after the last block are not committed.
+        let call = RuntimeCall::Balances(balances::Call::Transfer { to: alice, amount: 50 });
+        support::Dispatch::dispatch(&mut runtime, bob, call).unwrap();
+        assert_eq!(runtime.balances().get_balance(&bob), 0);
+        drop(runtime);
+
+        let runtime = super::Runtime::open(&dir).unwrap();
+        assert_eq!(runtime.system().block_number(), 2);
+        assert_eq!(runtime.system().get_nonce(&alice), 3);
+        assert_eq!(runtime.proof_of_existence().get_claim(&claim), Some(alice));
+        assert_eq!(runtime.balances().get_balance(&alice), 50);
+        assert_eq!(runtime.balances().get_balance(&bob), 50);
+        assert_eq!(runtime.balances().total_issuance(), 100);
+        assert_eq!(runtime.head_hash(), head_hash);
+        drop(runtime);
+
+        // Simulate a crash while committing block 2, after the System Pallet log was written but
+        // before the Balances Pallet one was.
+        let file = std::fs::OpenOptions::new().write(true).open(dir.join("balances.log")).unwrap();
+        file.set_len(balances_len).unwrap();
+        drop(file);
+
+        let mut runtime = super::Runtime::open(&dir).unwrap();
+        assert_eq!(runtime.system().block_number(), 1);
+        assert_eq!(runtime.system().get_nonce(&alice), 1);
+        assert_eq!(runtime.balances().get_balance(&alice), 70);
+        assert_eq!(runtime.proof_of_existence().get_claim(&claim), None);
+        assert_eq!(
+            runtime
+                .author_block(vec![transfer(genesis, "bob", 0, "alice", 10)])
+                .map(|block| block.header.number),
+            Ok(2)
+        );
+        drop(runtime);
+
+        let runtime = super::Runtime::open(&dir).unwrap();
+        assert_eq!(runtime.balances().get_balance(&alice), 80);
+        assert_eq!(runtime.system().get_nonce(&bob), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_commits_require_reopening() {
+        let dir = std::env::temp_dir()
+            .join(format!("state-machine-runtime-failure-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut genesis = RuntimeGenesisConfig::default();
+        genesis.balances.balances = vec![(account_id("alice"), 100)];
+
+        let mut runtime = super::Runtime::open(&dir).unwrap();
+        runtime.build_genesis(&genesis).unwrap();
+        let checkpoint = runtime.checkpoint();
+        let empty_block = block(&runtime, 1, vec![]);
+        // The Balances Pallet log cannot be appended to, so the block is only committed to the
+        // System Pallet one.
+        std::fs::remove_file(dir.join("balances.log")).unwrap();
+        std::fs::create_dir(dir.join("balances.log")).unwrap();
+        let failed = Err("A block could not be committed to disk, the runtime must be reopened.");
+        let extrinsic = transfer(checkpoint, "alice", 0, "bob", 30);
+        assert_eq!(runtime.author_block(vec![extrinsic.clone()]).map(|_| ()), failed);
+        assert_eq!(runtime.author_block(vec![]).map(|_| ()), failed);
+        assert_eq!(runtime.execute_block(empty_block), failed);
+        drop(runtime);
+
+        // Reopening drops the block from the System Pallet log, and it can be authored again.
+        std::fs::remove_dir(dir.join("balances.log")).unwrap();
+        let mut runtime = super::Runtime::open(&dir).unwrap();
+        assert_eq!(runtime.system().block_number(), 0);
+        runtime.build_genesis(&genesis).unwrap();
+        assert_eq!(runtime.checkpoint(), checkpoint);
+        assert!(runtime.author_block(vec![extrinsic]).is_ok());
+        assert_eq!(runtime.balances().get_balance(&account_id("bob")), 30);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn genesis_from_json() {
+        let (alice, bob) = (account_id("alice"), account_id("bob"));
+        let genesis = RuntimeGenesisConfig::from_json(&format!(
+            r#"{{
+                "system": {{ "block_number": 0 }},
+                "balances": {{ "balances": [["{alice}", 100], ["{bob}", 50]], "total_issuance": 150 }}
+            }}"#
+        ))
+        .unwrap();
+        let mut runtime = super::Runtime::new();
+        assert_eq!(runtime.build_genesis(&genesis), Ok(()));
+        assert_eq!(runtime.balances().get_balance(&account_id("alice")), 100);
+        assert_eq!(runtime.balances().total_issuance(), 150);
+        assert_eq!(runtime.build_genesis(&genesis), Err(GenesisError::StateNotEmpty));
+
+        // The genesis identifies the chain, so extrinsics signed for another one are not valid.
+        assert_eq!(runtime.head_hash(), runtime.state_root());
+        assert_eq!(
+            runtime.clone().author_block(vec![transfer(GENESIS, "alice", 0, "bob", 30)]),
+            Err("Extrinsic signature is invalid.")
+        );
+        let extrinsic = transfer(runtime.checkpoint(), "alice", 0, "bob", 30);
+        let res = runtime.execute_block(block(&runtime, 1, vec![extrinsic]));
+        assert_eq!(res, Ok(()));
+        let snapshot = runtime.snapshot();
+        assert_eq!(snapshot.state_root, runtime.state_root());
+        assert_eq!(snapshot.system.block_number, 1);
+        assert_eq!(snapshot.system.nonces, vec![(account_id("alice"), 1)]);
+        assert!(
+            snapshot
+                .balances
+                .accounts
+                .contains(&(bob, balances::AccountData { free: 80, reserved: 0 }))
+        );
+        let json = snapshot.to_json();
+        assert_eq!(serde_json::from_str::<RuntimeSnapshot>(&json).unwrap(), snapshot);
+        // Hashes are written in hexadecimal, like accounts.
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let hex = |hash| crypto::Public(hash).to_string();
+        assert_eq!(value["state_root"], hex(snapshot.state_root));
+        assert_eq!(value["system"]["block_hashes"][0][1], hex(snapshot.system.block_hashes[0].1));
+
+        // Invalid genesis configs are rejected as a whole.
+        let mut runtime = super::Runtime::new();
+        let mut genesis = RuntimeGenesisConfig::default();
+        genesis.balances.balances = vec![(account_id("alice"), 100), (account_id("alice"), 50)];
+        assert_eq!(
+            runtime.build_genesis(&genesis),
+            Err(GenesisError::DuplicateAccount(account_id("alice")))
+        );
+        genesis.balances.balances =
+            vec![(account_id("alice"), 100), (account_id("bob"), u128::MAX)];
+        assert_eq!(
+            runtime.build_genesis(&genesis),
+            Err(GenesisError::Balances(balances::Error::Overflow))
+        );
+        genesis.balances.balances = vec![(account_id("alice"), 100)];
+        genesis.balances.total_issuance = Some(150);
+        assert_eq!(
+            runtime.build_genesis(&genesis),
+            Err(GenesisError::TotalIssuanceMismatch { expected: 150, actual: 100 })
+        );
+        assert_eq!(runtime.state_root(), super::Runtime::new().state_root());
+        assert!(runtime.system().events().is_empty());
+        assert!(RuntimeGenesisConfig::from_json(r#"{ "sytem": {} }"#).is_err());
+    }
+
+    fn account() -> impl Strategy<Value = types::AccountId> {
+        any::<[u8; 32]>().prop_map(crypto::Public)
+    }
+
+    fn call() -> impl Strategy<Value = RuntimeCall> {
+        prop_oneof![
+            (account(), any::<u128>()).prop_map(|(to, amount)| {
+                RuntimeCall::Balances(balances::Call::Transfer { to, amount })
+            }),
+            (account(), any::<u128>()).prop_map(|(spender, amount)| {
+                RuntimeCall::Balances(balances::Call::Approve { spender, amount })
+            }),
+            (account(), account(), any::<u128>()).prop_map(|(owner, to, amount)| {
+                RuntimeCall::Balances(balances::Call::TransferFrom { owner, to, amount })
+            }),
+            prop::collection::vec((account(), any::<u128>()), 0..4).prop_map(|transfers| {
+                RuntimeCall::Balances(balances::Call::TransferMany { transfers })
+            }),
+            prop::collection::vec(any::<u8>(), 0..100).prop_map(|claim| {
+                RuntimeCall::ProofOfExistence(proof_of_existence::Call::CreateClaim { claim })
+            }),
+            prop::collection::vec(any::<u8>(), 0..100).prop_map(|claim| {
+                RuntimeCall::ProofOfExistence(proof_of_existence::Call::RevokeClaim { claim })
+            }),
+        ]
+    }
+
+    /// An extrinsic, together with the hash of its checkpoint.
+    fn extrinsic_with_checkpoint_hash() -> impl Strategy<Value = (types::Extrinsic, types::Hash)> {
+        let checkpoint = (any::<u32>(), any::<[u8; 32]>());
+        (any::<[u8; 32]>(), any::<u32>(), checkpoint, call()).prop_map(
+            |(seed, nonce, checkpoint, call)| {
+                let pair = crypto::Pair::from_seed(&seed);
+                (support::Extrinsic::new_signed(&pair, nonce, checkpoint, call), checkpoint.1)
+            },
+        )
+    }
+
+    fn extrinsic() -> impl Strategy<Value = types::Extrinsic> {
+        extrinsic_with_checkpoint_hash().prop_map(|(extrinsic, _)| extrinsic)
+    }
+
+    fn any_block() -> impl Strategy<Value = types::Block> {
+        let header = (any::<[u8; 32]>(), any::<u32>(), any::<[u8; 32]>(), any::<[u8; 32]>())
+            .prop_map(|(parent_hash, number, extrinsics_root, state_root)| support::Header {
+                parent_hash,
+                number,
+                extrinsics_root,
+                state_root,
+            });
+        (header, prop::collection::vec(extrinsic(), 0..8))
+            .prop_map(|(header, extrinsics)| support::Block { header, extrinsics })
+    }
+
+    #[test]
+    fn call_encoding() {
+        let call =
+            RuntimeCall::Balances(balances::Call::Transfer { to: account_id("bob"), amount: 5 });
+        let mut expected = vec![1, 0];
+        expected.extend(account_id("bob").0);
+        expected.push(5);
+        expected.extend([0; 15]);
+        assert_eq!(call.encode(), expected);
+
+        assert_eq!(decode_all::<RuntimeCall>(&[0, 0]), Err(CodecError("invalid pallet index")));
+        assert_eq!(decode_all::<RuntimeCall>(&[2, 7]), Err(CodecError("invalid call index")));
+    }
+
+    proptest! {
+        #[test]
+        fn extrinsics_round_trip((extrinsic, hash) in extrinsic_with_checkpoint_hash()) {
+            prop_assert!(extrinsic.verify::<crypto::Pair>(&hash));
+            prop_assert!(!extrinsic.verify::<crypto::Pair>(&crate::merkle::hash(&hash)));
+            prop_assert_eq!(decode_all::<types::Extrinsic>(&extrinsic.encode()), Ok(extrinsic));
+        }
+
+        #[test]
+        fn blocks_round_trip(block in any_block()) {
+            let bytes = block.encode();
+            prop_assert_eq!(types::Block::decode(&mut &bytes[..]), Ok(block.clone()));
+            prop_assert_eq!(types::Header::decode(&mut &bytes[..]), Ok(block.header));
+            // Every strict prefix of the encoding is rejected.
+            for len in 0..bytes.len() {
+                prop_assert!(decode_all::<types::Block>(&bytes[..len]).is_err());
+            }
+        }
+    }
+}
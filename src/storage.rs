@@ -0,0 +1,848 @@
+//! Storage primitives for the pallets.
+//!
+//! Every change made to pallet storage is recorded in an overlay belonging to the innermost open
+//! transaction. Committing a transaction merges its overlay into the enclosing one (or into the
+//! committed state), while rolling it back simply drops the overlay.
+//!
+//! Pallets keep their state in a key-value [`Storage`] backend, through the typed [`StorageValue`]
+//! and [`StorageMap`] wrappers which encode keys and values with the [`crate::codec`]. The state
+//! is either kept in memory only, or also persisted to disk by a [`FileStorage`].
+
+use crate::codec::{Decode, Encode, decode_all};
+use core::{fmt::Debug, marker::PhantomData, ops::Bound};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// A type whose changes can be grouped in nested transactions, which are then either committed or
+/// rolled back as a whole.
+pub trait Transactional {
+    /// Open a new transaction, nested in the currently open one (if any).
+    fn start_transaction(&mut self);
+    /// Keep the changes made since the innermost transaction was opened, and close it.
+    fn commit_transaction(&mut self);
+    /// Discard the changes made since the innermost transaction was opened, and close it.
+    fn rollback_transaction(&mut self);
+}
+
+/// Execute `f` in a new transaction on `state`: its changes are committed if it returns `Ok`, and
+/// rolled back if it returns `Err`. Transactions can be nested by calling `with_transaction` again
+/// from within `f`.
+pub fn with_transaction<S, R, E>(
+    state: &mut S,
+    f: impl FnOnce(&mut S) -> Result<R, E>,
+) -> Result<R, E>
+where
+    S: Transactional + ?Sized,
+{
+    state.start_transaction();
+    let res = f(state);
+    if res.is_ok() {
+        state.commit_transaction();
+    } else {
+        state.rollback_transaction();
+    }
+    res
+}
+
+/// A map from `K` to `V`, whose changes are recorded per open transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayedMap<K, V> {
+    /// The state as of the last commit of the outermost transaction.
+    committed: BTreeMap<K, V>,
+    /// The changes made in each open transaction, innermost last. `None` marks a removal.
+    overlays: Vec<BTreeMap<K, Option<V>>>,
+}
+
+impl<K: Ord + Clone, V: Clone> OverlayedMap<K, V> {
+    /// Create a new, empty map.
+    pub fn new() -> Self {
+        Self { committed: BTreeMap::new(), overlays: Vec::new() }
+    }
+
+    /// Get the value at `key`, taking the changes of all open transactions into account.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        for overlay in self.overlays.iter().rev() {
+            if let Some(change) = overlay.get(key) {
+                return change.as_ref();
+            }
+        }
+        self.committed.get(key)
+    }
+
+    /// Whether there is a value at `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert `value` at `key`, returning the previous value if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.get(&key).cloned();
+        match self.overlays.last_mut() {
+            Some(overlay) => {
+                overlay.insert(key, Some(value));
+            },
+            None => {
+                self.committed.insert(key, value);
+            },
+        }
+        previous
+    }
+
+    /// Remove the value at `key`, returning it if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let previous = self.get(key).cloned();
+        match self.overlays.last_mut() {
+            Some(overlay) => {
+                overlay.insert(key.clone(), None);
+            },
+            None => {
+                self.committed.remove(key);
+            },
+        }
+        previous
+    }
+
+    /// Iterate over all the entries of the map in key order, taking the changes of all open
+    /// transactions into account.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut entries: BTreeMap<&K, Option<&V>> =
+            self.committed.iter().map(|(k, v)| (k, Some(v))).collect();
+        for overlay in &self.overlays {
+            entries.extend(overlay.iter().map(|(k, v)| (k, v.as_ref())));
+        }
+        entries.into_iter().filter_map(|(k, v)| Some((k, v?)))
+    }
+
+    /// Iterate over the entries of the map whose key is at least `start`, in key order, taking the
+    /// changes of all open transactions into account.
+    pub fn iter_from(&self, start: &K) -> impl Iterator<Item = (&K, &V)> {
+        let range = (Bound::Included(start), Bound::Unbounded);
+        let mut entries: BTreeMap<&K, Option<&V>> =
+            self.committed.range::<K, _>(range).map(|(k, v)| (k, Some(v))).collect();
+        for overlay in &self.overlays {
+            entries.extend(overlay.range::<K, _>(range).map(|(k, v)| (k, v.as_ref())));
+        }
+        entries.into_iter().filter_map(|(k, v)| Some((k, v?)))
+    }
+
+    /// Keep only the entries for which `f` returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let removed: Vec<K> =
+            self.iter().filter(|(k, v)| !f(k, v)).map(|(k, _)| k.clone()).collect();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for OverlayedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, V> Transactional for OverlayedMap<K, V> {
+    fn start_transaction(&mut self) {
+        self.overlays.push(BTreeMap::new());
+    }
+
+    fn commit_transaction(&mut self) {
+        let overlay = self.overlays.pop().expect("no open transaction to commit");
+        match self.overlays.last_mut() {
+            Some(parent) => parent.extend(overlay),
+            None =>
+                for (key, change) in overlay {
+                    match change {
+                        Some(value) => self.committed.insert(key, value),
+                        None => self.committed.remove(&key),
+                    };
+                },
+        }
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.overlays.pop().expect("no open transaction to roll back");
+    }
+}
+
+/// A single value of type `V`, whose changes are recorded per open transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayedValue<V> {
+    /// The value as of the last commit of the outermost transaction.
+    committed: V,
+    /// The value set in each open transaction, innermost last, if any.
+    overlays: Vec<Option<V>>,
+}
+
+impl<V> OverlayedValue<V> {
+    /// Create a new value, initially set to `value`.
+    pub fn new(value: V) -> Self {
+        Self { committed: value, overlays: Vec::new() }
+    }
+
+    /// Get the value, taking the changes of all open transactions into account.
+    pub fn get(&self) -> &V {
+        self.overlays.iter().rev().find_map(Option::as_ref).unwrap_or(&self.committed)
+    }
+
+    /// Set the value.
+    pub fn set(&mut self, value: V) {
+        match self.overlays.last_mut() {
+            Some(overlay) => *overlay = Some(value),
+            None => self.committed = value,
+        }
+    }
+}
+
+impl<V> Transactional for OverlayedValue<V> {
+    fn start_transaction(&mut self) {
+        self.overlays.push(None);
+    }
+
+    fn commit_transaction(&mut self) {
+        let overlay = self.overlays.pop().expect("no open transaction to commit");
+        if let Some(value) = overlay {
+            match self.overlays.last_mut() {
+                Some(parent) => *parent = Some(value),
+                None => self.committed = value,
+            }
+        }
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.overlays.pop().expect("no open transaction to roll back");
+    }
+}
+
+/// An append-only list of `E`, e.g. events, whose entries pushed within a transaction are dropped
+/// if it is rolled back. The log can also be cleared, which is undone as well by a rollback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayedLog<E> {
+    entries: Vec<E>,
+    /// The state of the log when each open transaction was started, innermost last.
+    marks: Vec<LogMark<E>>,
+}
+
+/// The state of an [`OverlayedLog`] when a transaction was started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LogMark<E> {
+    /// The log held that many entries, and has not been cleared since.
+    Len(usize),
+    /// The log held these entries, and has been cleared since.
+    Cleared(Vec<E>),
+}
+
+impl<E> OverlayedLog<E> {
+    /// Create a new, empty log.
+    pub fn new() -> Self {
+        Self { entries: Vec::new(), marks: Vec::new() }
+    }
+
+    /// Get all the entries of the log.
+    pub fn entries(&self) -> &[E] {
+        &self.entries
+    }
+
+    /// Append `entry` to the log.
+    pub fn push(&mut self, entry: E) {
+        self.entries.push(entry);
+    }
+
+    /// Remove and return all the entries of the log. Within a transaction, the entries are kept
+    /// until it is committed, so that rolling it back restores them.
+    pub fn take(&mut self) -> Vec<E>
+    where
+        E: Clone,
+    {
+        let entries = if self.marks.is_empty() {
+            core::mem::take(&mut self.entries)
+        } else {
+            self.entries.clone()
+        };
+        self.clear();
+        entries
+    }
+
+    /// Remove all the entries of the log.
+    pub fn clear(&mut self) {
+        let mut entries = core::mem::take(&mut self.entries);
+        // The innermost transaction keeps the entries it started with. Those of the outer
+        // transactions are a prefix of them, which it hands over when it is committed.
+        if let Some(mark) = self.marks.last_mut() &&
+            let LogMark::Len(len) = *mark
+        {
+            entries.truncate(len);
+            *mark = LogMark::Cleared(entries);
+        }
+    }
+}
+
+impl<E> Default for OverlayedLog<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Transactional for OverlayedLog<E> {
+    fn start_transaction(&mut self) {
+        self.marks.push(LogMark::Len(self.entries.len()));
+    }
+
+    fn commit_transaction(&mut self) {
+        let mark = self.marks.pop().expect("no open transaction to commit");
+        if let (LogMark::Cleared(mut entries), Some(parent)) = (mark, self.marks.last_mut()) &&
+            let LogMark::Len(len) = *parent
+        {
+            entries.truncate(len);
+            *parent = LogMark::Cleared(entries);
+        }
+    }
+
+    fn rollback_transaction(&mut self) {
+        match self.marks.pop().expect("no open transaction to roll back") {
+            LogMark::Len(len) => self.entries.truncate(len),
+            LogMark::Cleared(entries) => self.entries = entries,
+        }
+    }
+}
+
+/// A key-value store of raw bytes, in which the pallets keep their state.
+pub trait Storage {
+    /// Get the value at `key`, if any.
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+    /// Set the value at `key` to `value`.
+    fn set(&mut self, key: &[u8], value: Vec<u8>);
+    /// Remove the value at `key`, if any.
+    fn remove(&mut self, key: &[u8]);
+    /// Get all the entries whose key starts with `prefix`, in key order.
+    fn iter_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)>;
+    /// Persist the changes made since the last commit, e.g. at the end of a block. Does nothing for
+    /// backends which are not persisted.
+    ///
+    /// Must not be called while a transaction is open.
+    fn commit(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [`Storage`] backend which keeps everything in memory. It supports nested transactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InMemoryStorage {
+    entries: OverlayedMap<Vec<u8>, Vec<u8>>,
+}
+
+impl InMemoryStorage {
+    /// Create a new, empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.get(&key.to_vec()).cloned()
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) {
+        self.entries.insert(key.to_vec(), value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.entries.remove(&key.to_vec());
+    }
+
+    fn iter_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.entries
+            .iter_from(&prefix.to_vec())
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl Transactional for InMemoryStorage {
+    fn start_transaction(&mut self) {
+        self.entries.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.entries.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.entries.rollback_transaction();
+    }
+}
+
+/// The changes of one commit of a [`FileStorage`]: the new value of every key written since the
+/// previous commit, or `None` if it was removed.
+type ChangeSet = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// A [`Storage`] backend which keeps everything in memory, like [`InMemoryStorage`], and persists
+/// it to an append-only log file.
+///
+/// Changes are only written to the file by [`FileStorage::commit`], which appends a record with
+/// every key changed since the previous commit. Each record is prefixed with its length and a
+/// checksum, so that a record torn by a crash is detected and discarded when the file is opened
+/// again: the state is recovered as of the last complete commit.
+///
+/// If a record cannot be fully written, it is truncated from the file again, so that the next
+/// commit is not appended after a torn record. Should that fail too, the storage refuses any
+/// further commit, and must be opened again.
+///
+/// A storage created with [`FileStorage::default`] has no file, and its commits are only counted.
+/// So has a clone of a storage, which otherwise would append its commits to the same file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FileStorage {
+    /// The current state, including the changes which are not committed to the file yet.
+    state: InMemoryStorage,
+    /// The keys changed since the last commit.
+    dirty: BTreeSet<Vec<u8>>,
+    /// The log file, if any.
+    path: Option<PathBuf>,
+    /// The number of commits made so far, including the ones replayed from the file.
+    commits: u64,
+    /// Whether a torn record may be left at the end of the file, after a failed commit.
+    failed: bool,
+}
+
+impl Clone for FileStorage {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            dirty: self.dirty.clone(),
+            path: None,
+            commits: self.commits,
+            failed: false,
+        }
+    }
+}
+
+impl FileStorage {
+    /// Open the log file at `path`, creating it if it does not exist, and recover the state from
+    /// at most `max_commits` of its commits (or all of them if `None`). Anything after them,
+    /// including a torn record, is truncated from the file.
+    pub fn open(path: impl AsRef<Path>, max_commits: Option<u64>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut storage = Self { path: Some(path.to_path_buf()), ..Self::default() };
+        let mut len = 0;
+        for (end, changes) in read_records(&bytes) {
+            if max_commits.is_some_and(|max| storage.commits >= max) {
+                break;
+            }
+            for (key, value) in changes {
+                match value {
+                    Some(value) => storage.state.set(&key, value),
+                    None => storage.state.remove(&key),
+                }
+            }
+            storage.commits += 1;
+            len = end;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if len < bytes.len() {
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+        Ok(storage)
+    }
+
+    /// Count the complete commits in the log file at `path`, without opening it. A missing file
+    /// has none.
+    pub fn count_commits(path: impl AsRef<Path>) -> io::Result<u64> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(read_records(&bytes).count() as u64),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The number of commits made so far.
+    pub fn commits(&self) -> u64 {
+        self.commits
+    }
+}
+
+/// Iterate over the complete records of a log file, yielding the offset at which each one ends and
+/// its changes. Stops at the first record which is incomplete or corrupt.
+fn read_records(bytes: &[u8]) -> impl Iterator<Item = (usize, ChangeSet)> + '_ {
+    let mut offset = 0;
+    core::iter::from_fn(move || {
+        let mut input = &bytes[offset..];
+        let len = u32::decode(&mut input).ok()? as usize;
+        let sum = u64::decode(&mut input).ok()?;
+        let payload = input.get(..len)?;
+        if checksum(payload) != sum {
+            return None;
+        }
+        let changes = decode_all(payload).ok()?;
+        offset += 12 + len;
+        Some((offset, changes))
+    })
+}
+
+/// The 64 bit FNV-1a hash of `bytes`, used to detect torn or corrupt records.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.state.get(key)
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) {
+        self.dirty.insert(key.to_vec());
+        self.state.set(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.dirty.insert(key.to_vec());
+        self.state.remove(key);
+    }
+
+    fn iter_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.state.iter_prefix(prefix)
+    }
+
+    /// Append the changes made since the last commit to the log file, and wait for them to reach
+    /// the disk. A record is appended even if nothing changed, so that the commits of storages
+    /// which are committed together stay aligned.
+    ///
+    /// On failure, nothing is committed and the changes are kept, to be committed again.
+    fn commit(&mut self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("a previous commit failed, the log must be reopened"));
+        }
+        if let Some(path) = &self.path {
+            let changes: ChangeSet =
+                self.dirty.iter().map(|key| (key.clone(), self.state.get(key))).collect();
+            let payload = changes.encode();
+            let mut record = Vec::with_capacity(payload.len() + 12);
+            (payload.len() as u32).encode_to(&mut record);
+            checksum(&payload).encode_to(&mut record);
+            record.extend_from_slice(&payload);
+
+            let mut file = OpenOptions::new().append(true).open(path)?;
+            let len = file.metadata()?.len();
+            if let Err(e) = file.write_all(&record).and_then(|()| file.sync_data()) {
+                // Remove whatever part of the record was written.
+                self.failed = file.set_len(len).and_then(|()| file.sync_data()).is_err();
+                return Err(e);
+            }
+        }
+        self.dirty.clear();
+        self.commits += 1;
+        Ok(())
+    }
+}
+
+// A key changed in a transaction which is rolled back stays dirty: committing its current value
+// again is harmless.
+impl Transactional for FileStorage {
+    fn start_transaction(&mut self) {
+        self.state.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.state.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.state.rollback_transaction();
+    }
+}
+
+/// Decode a value read from storage. Everything in storage was written through the typed wrappers
+/// below, so a value which does not decode means the storage is corrupt.
+fn decode_stored<V: Decode>(bytes: &[u8]) -> V {
+    decode_all(bytes).expect("storage is corrupt")
+}
+
+/// A single typed value, kept in storage at a fixed key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageValue<V> {
+    key: &'static [u8],
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V: Encode + Decode> StorageValue<V> {
+    /// Declare a value kept at `key`, which must be unique across the storage.
+    pub const fn new(key: &'static [u8]) -> Self {
+        Self { key, _value: PhantomData }
+    }
+
+    /// Get the value, if it is set.
+    pub fn get(&self, storage: &impl Storage) -> Option<V> {
+        storage.get(self.key).map(|bytes| decode_stored(&bytes))
+    }
+
+    /// Set the value.
+    pub fn set(&self, storage: &mut impl Storage, value: &V) {
+        storage.set(self.key, value.encode());
+    }
+
+    /// Remove the value.
+    pub fn remove(&self, storage: &mut impl Storage) {
+        storage.remove(self.key);
+    }
+}
+
+/// A typed map from `K` to `V`, kept in storage under a fixed prefix. Each entry is stored at the
+/// prefix followed by the encoding of its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageMap<K, V> {
+    prefix: &'static [u8],
+    _entry: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: Encode + Decode, V: Encode + Decode> StorageMap<K, V> {
+    /// Declare a map kept under `prefix`, which must not be a prefix of any other key or prefix in
+    /// the storage.
+    pub const fn new(prefix: &'static [u8]) -> Self {
+        Self { prefix, _entry: PhantomData }
+    }
+
+    /// The storage key of the entry at `key`.
+    pub fn storage_key(&self, key: &K) -> Vec<u8> {
+        let mut storage_key = self.prefix.to_vec();
+        key.encode_to(&mut storage_key);
+        storage_key
+    }
+
+    /// Get the value at `key`, if any.
+    pub fn get(&self, storage: &impl Storage, key: &K) -> Option<V> {
+        storage.get(&self.storage_key(key)).map(|bytes| decode_stored(&bytes))
+    }
+
+    /// Whether there is a value at `key`.
+    pub fn contains_key(&self, storage: &impl Storage, key: &K) -> bool {
+        storage.get(&self.storage_key(key)).is_some()
+    }
+
+    /// Insert `value` at `key`.
+    pub fn insert(&self, storage: &mut impl Storage, key: &K, value: &V) {
+        storage.set(&self.storage_key(key), value.encode());
+    }
+
+    /// Remove the value at `key`, if any.
+    pub fn remove(&self, storage: &mut impl Storage, key: &K) {
+        storage.remove(&self.storage_key(key));
+    }
+
+    /// Get all the entries of the map, in the order of their encoded keys.
+    pub fn iter(&self, storage: &impl Storage) -> Vec<(K, V)> {
+        storage
+            .iter_prefix(self.prefix)
+            .into_iter()
+            .map(|(key, value)| (decode_stored(&key[self.prefix.len()..]), decode_stored(&value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FileStorage, InMemoryStorage, OverlayedLog, OverlayedMap, OverlayedValue, Storage,
+        StorageMap, StorageValue, Transactional, with_transaction,
+    };
+    use std::{fs, io::Write};
+
+    #[test]
+    fn nested_transactions() {
+        let mut map = OverlayedMap::<&str, u32>::new();
+        map.insert("alice", 1);
+        map.insert("bob", 2);
+
+        let res: Result<(), ()> = with_transaction(&mut map, |map| {
+            map.insert("alice", 10);
+            map.remove(&"bob");
+
+            // The inner transaction fails, so only its own changes are discarded.
+            let inner: Result<(), ()> = with_transaction(map, |map| {
+                map.insert("charlie", 3);
+                map.insert("alice", 100);
+                Err(())
+            });
+            assert_eq!(inner, Err(()));
+            assert_eq!(map.get(&"alice"), Some(&10));
+            assert!(!map.contains_key(&"charlie"));
+
+            with_transaction(map, |map| {
+                map.insert("dave", 4);
+                Ok(())
+            })
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&"alice", &10), (&"dave", &4)]);
+
+        let res: Result<(), ()> = with_transaction(&mut map, |map| {
+            map.retain(|_, value| *value > 5);
+            assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&"alice", &10)]);
+            Err(())
+        });
+        assert_eq!(res, Err(()));
+        assert_eq!(map.get(&"dave"), Some(&4));
+    }
+
+    #[test]
+    fn overlayed_value_and_log() {
+        let mut value = OverlayedValue::new(1);
+        let mut log = OverlayedLog::new();
+        log.push("before");
+
+        value.start_transaction();
+        log.start_transaction();
+        value.set(2);
+        log.push("committed");
+        value.commit_transaction();
+        log.commit_transaction();
+
+        value.start_transaction();
+        log.start_transaction();
+        value.set(3);
+        log.push("rolled back");
+        assert_eq!(*value.get(), 3);
+        value.rollback_transaction();
+        log.rollback_transaction();
+
+        assert_eq!(*value.get(), 2);
+        assert_eq!(log.entries(), &["before", "committed"]);
+    }
+
+    #[test]
+    fn overlayed_log_clear_is_rolled_back() {
+        let mut log = OverlayedLog::new();
+        log.push("first");
+        log.push("second");
+
+        log.start_transaction();
+        log.clear();
+        log.push("rolled back");
+        log.rollback_transaction();
+        assert_eq!(log.entries(), &["first", "second"]);
+
+        // Clearing in a nested transaction is only final once every transaction is committed.
+        log.start_transaction();
+        log.push("third");
+        log.start_transaction();
+        assert_eq!(log.take(), vec!["first", "second", "third"]);
+        log.push("fourth");
+        log.commit_transaction();
+        assert_eq!(log.entries(), &["fourth"]);
+        log.rollback_transaction();
+        assert_eq!(log.entries(), &["first", "second"]);
+
+        log.start_transaction();
+        log.clear();
+        log.push("committed");
+        log.commit_transaction();
+        assert_eq!(log.entries(), &["committed"]);
+        assert_eq!(log.take(), vec!["committed"]);
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn typed_storage() {
+        const NUMBER: StorageValue<u32> = StorageValue::new(b"Test/Number");
+        const NAMES: StorageMap<u32, String> = StorageMap::new(b"Test/Names/");
+        const OTHER: StorageMap<u32, String> = StorageMap::new(b"Other/");
+
+        let mut storage = InMemoryStorage::new();
+        assert_eq!(NUMBER.get(&storage), None);
+        NUMBER.set(&mut storage, &7);
+        assert_eq!(NUMBER.get(&storage), Some(7));
+
+        NAMES.insert(&mut storage, &2, &"bob".to_string());
+        NAMES.insert(&mut storage, &1, &"alice".to_string());
+        OTHER.insert(&mut storage, &3, &"charlie".to_string());
+        assert!(NAMES.contains_key(&storage, &1));
+        assert_eq!(NAMES.iter(&storage), vec![(1, "alice".to_string()), (2, "bob".to_string())]);
+
+        let res: Result<(), ()> = with_transaction(&mut storage, |storage| {
+            NAMES.remove(storage, &1);
+            NUMBER.remove(storage);
+            assert_eq!(storage.iter_prefix(b"Test/").len(), 1);
+            Err(())
+        });
+        assert_eq!(res, Err(()));
+        assert_eq!(NAMES.get(&storage, &1), Some("alice".to_string()));
+        assert_eq!(NUMBER.get(&storage), Some(7));
+        assert_eq!(OTHER.iter(&storage), vec![(3, "charlie".to_string())]);
+    }
+
+    #[test]
+    fn file_storage() {
+        let dir =
+            std::env::temp_dir().join(format!("state-machine-storage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let _ = fs::remove_file(&path);
+
+        let mut storage = FileStorage::open(&path, None).unwrap();
+        storage.set(b"a", vec![1]);
+        storage.set(b"b", vec![2]);
+        storage.commit().unwrap();
+        storage.remove(b"a");
+        let _: Result<(), ()> = with_transaction(&mut storage, |storage| {
+            storage.set(b"c", vec![3]);
+            Err(())
+        });
+        storage.commit().unwrap();
+        // Not committed, so lost on reopening.
+        storage.set(b"d", vec![4]);
+        // A clone is only kept in memory, so its commits are not written to the file.
+        let mut fork = storage.clone();
+        fork.commit().unwrap();
+        assert_eq!(fork.commits(), 3);
+
+        let reopened = FileStorage::open(&path, None).unwrap();
+        assert_eq!(reopened.commits(), 2);
+        assert_eq!(reopened.iter_prefix(b""), vec![(b"b".to_vec(), vec![2])]);
+
+        // A torn record at the end of the file is discarded.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[9, 0, 0])
+            .unwrap();
+        assert_eq!(FileStorage::count_commits(&path).unwrap(), 2);
+        let reopened = FileStorage::open(&path, None).unwrap();
+        assert_eq!(reopened.iter_prefix(b""), vec![(b"b".to_vec(), vec![2])]);
+
+        // Only the first commits can be replayed, and the later ones are dropped.
+        let reopened = FileStorage::open(&path, Some(1)).unwrap();
+        assert_eq!(reopened.get(b"a"), Some(vec![1]));
+        assert_eq!(FileStorage::count_commits(&path).unwrap(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn file_storage_commit_failure() {
+        // Writing to `/dev/full` always fails, and so does truncating it.
+        let mut storage = FileStorage { path: Some("/dev/full".into()), ..FileStorage::default() };
+        storage.set(b"a", vec![1]);
+        assert_eq!(storage.commit().unwrap_err().kind(), std::io::ErrorKind::StorageFull);
+        assert_eq!(storage.commits(), 0);
+        // The log may now end with a torn record, which no later commit may be appended after.
+        assert_eq!(storage.commit().unwrap_err().kind(), std::io::ErrorKind::Other);
+        assert_eq!(storage.commits(), 0);
+        assert_eq!(storage.get(b"a"), Some(vec![1]));
+    }
+}
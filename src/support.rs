@@ -0,0 +1,236 @@
+//! Primitive types and traits shared by the pallets and the runtime.
+
+use crate::{
+    codec::{Decode, Encode, Error as CodecError},
+    merkle,
+};
+
+/// The most primitive representation of a Blockchain block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block<Header, Extrinsic> {
+    /// The block header contains metadata about the block.
+    pub header: Header,
+    /// The extrinsics represent the state transitions to be executed in this block.
+    pub extrinsics: Vec<Extrinsic>,
+}
+
+impl<Header: Encode, Extrinsic: Encode> Encode for Block<Header, Extrinsic> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.header.encode_to(dest);
+        self.extrinsics.encode_to(dest);
+    }
+}
+
+impl<Header: Decode, Extrinsic: Decode> Decode for Block<Header, Extrinsic> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self { header: Header::decode(input)?, extrinsics: Vec::decode(input)? })
+    }
+}
+
+/// The header of a block, which identifies it: its hash is the hash of the block.
+/// On a real blockchain, you would expect to also find a digest with consensus information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header<BlockNumber, Hash> {
+    /// The hash of the previous block, which chains the blocks together.
+    pub parent_hash: Hash,
+    /// The number of the block, i.e. its height in the chain.
+    pub number: BlockNumber,
+    /// The root of the Merkle tree over the hashes of the extrinsics of the block, so that the
+    /// header commits to them.
+    pub extrinsics_root: Hash,
+    /// The root of the Merkle tree over the state after executing the block, so that anyone
+    /// executing the block can check that they end up with the same state.
+    pub state_root: Hash,
+}
+
+impl<BlockNumber: Encode, Hash: Encode> Header<BlockNumber, Hash> {
+    /// The hash of the header, which is the hash of the block.
+    pub fn hash(&self) -> merkle::Hash {
+        merkle::hash(&self.encode())
+    }
+}
+
+impl<BlockNumber: Encode, Hash: Encode> Encode for Header<BlockNumber, Hash> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.parent_hash.encode_to(dest);
+        self.number.encode_to(dest);
+        self.extrinsics_root.encode_to(dest);
+        self.state_root.encode_to(dest);
+    }
+}
+
+impl<BlockNumber: Decode, Hash: Decode> Decode for Header<BlockNumber, Hash> {
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {
+            parent_hash: Hash::decode(input)?,
+            number: BlockNumber::decode(input)?,
+            extrinsics_root: Hash::decode(input)?,
+            state_root: Hash::decode(input)?,
+        })
+    }
+}
+
+/// This is an "extrinsic": literally an external message from outside of the blockchain.
+/// This simplified version of an extrinsic tells us who is making the call, and which call they are
+/// making. It is signed by the key of the caller, so that nobody else can make calls on their
+/// behalf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extrinsic<AccountId, Nonce, BlockNumber, Signature, Call> {
+    /// The account making the call, i.e. the public key which signed the extrinsic.
+    pub signer: AccountId,
+    /// The number of extrinsics made by the signer before this one, so that the same extrinsic
+    /// cannot be executed twice.
+    pub nonce: Nonce,
+    /// The number of a recent block, whose hash is signed along with the call. The extrinsic is
+    /// only valid on the chain containing that block, and only for a limited number of blocks
+    /// after it.
+    pub checkpoint: BlockNumber,
+    /// The signature of the nonce, the checkpoint and the call, see
+    /// [`Extrinsic::signing_payload`].
+    pub signature: Signature,
+    pub call: Call,
+}
+
+impl<AccountId, Nonce: Encode, BlockNumber: Encode, Signature, Call: Encode>
+    Extrinsic<AccountId, Nonce, BlockNumber, Signature, Call>
+{
+    /// Build the extrinsic making `call` with `nonce`, signed by `pair` on top of the block with
+    /// the given `checkpoint` number and hash.
+    pub fn new_signed<P>(
+        pair: &P,
+        nonce: Nonce,
+        checkpoint: (BlockNumber, merkle::Hash),
+        call: Call,
+    ) -> Self
+    where
+        P: Pair<Public = AccountId, Signature = Signature>,
+    {
+        let (checkpoint, checkpoint_hash) = checkpoint;
+        let payload = Self::signing_payload(&nonce, &checkpoint, &checkpoint_hash, &call);
+        Self { signer: pair.public(), nonce, checkpoint, signature: pair.sign(&payload), call }
+    }
+
+    /// The message signed by the signer: the encoded nonce, checkpoint number and hash, and call.
+    /// The nonce is signed too, so that the signature cannot be replayed with another nonce, and so
+    /// is the hash of the checkpoint, so that it cannot be replayed on another chain.
+    pub fn signing_payload(
+        nonce: &Nonce,
+        checkpoint: &BlockNumber,
+        checkpoint_hash: &merkle::Hash,
+        call: &Call,
+    ) -> Vec<u8> {
+        (nonce, checkpoint, checkpoint_hash, call).encode()
+    }
+
+    /// Whether the signature is valid for the nonce and call of this extrinsic, under the key of
+    /// the signer, given `checkpoint_hash`, the hash of the block at its checkpoint.
+    pub fn verify<P>(&self, checkpoint_hash: &merkle::Hash) -> bool
+    where
+        P: Pair<Public = AccountId, Signature = Signature>,
+    {
+        let payload =
+            Self::signing_payload(&self.nonce, &self.checkpoint, checkpoint_hash, &self.call);
+        P::verify(&self.signature, &payload, &self.signer)
+    }
+}
+
+impl<AccountId: Encode, Nonce: Encode, BlockNumber: Encode, Signature: Encode, Call: Encode> Encode
+    for Extrinsic<AccountId, Nonce, BlockNumber, Signature, Call>
+{
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.signer.encode_to(dest);
+        self.nonce.encode_to(dest);
+        self.checkpoint.encode_to(dest);
+        self.signature.encode_to(dest);
+        self.call.encode_to(dest);
+    }
+}
+
+impl<AccountId: Decode, Nonce: Decode, BlockNumber: Decode, Signature: Decode, Call: Decode> Decode
+    for Extrinsic<AccountId, Nonce, BlockNumber, Signature, Call>
+{
+    fn decode(input: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {
+            signer: AccountId::decode(input)?,
+            nonce: Nonce::decode(input)?,
+            checkpoint: BlockNumber::decode(input)?,
+            signature: Signature::decode(input)?,
+            call: Call::decode(input)?,
+        })
+    }
+}
+
+/// A key pair which signs messages on behalf of its public key, together with the scheme to
+/// verify such signatures, e.g. [`crate::crypto::Pair`].
+pub trait Pair {
+    /// The public key, which identifies the signer.
+    type Public;
+    /// A signature made by the key pair.
+    type Signature;
+
+    /// The public key of this key pair.
+    fn public(&self) -> Self::Public;
+
+    /// Sign `message` with the secret key of this key pair.
+    fn sign(&self, message: &[u8]) -> Self::Signature;
+
+    /// Whether `signature` is a signature of `message` by the key pair with the key `public`.
+    fn verify(signature: &Self::Signature, message: &[u8], public: &Self::Public) -> bool;
+}
+
+/// The root of the Merkle tree whose leaves hold the encoded `extrinsics`, in order.
+pub fn extrinsics_root<E: Encode>(extrinsics: &[E]) -> merkle::Hash {
+    let leaves: Vec<_> = extrinsics
+        .iter()
+        .map(|extrinsic| merkle::data_leaf_hash(&extrinsic.encode()))
+        .collect();
+    merkle::merkle_root(&leaves)
+}
+
+/// A measure of the time it takes to execute a call, which it pays fees for.
+pub type Weight = u64;
+
+/// A call whose weight is known before it is dispatched.
+pub trait GetDispatchInfo {
+    /// The weight charged for this call before dispatching it, i.e. an upper bound of the work it
+    /// may do.
+    fn weight(&self) -> Weight;
+}
+
+/// What is known about a call once it has been dispatched successfully.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PostDispatchInfo {
+    /// The weight the call actually consumed, if it is known to be less than its
+    /// [weight](GetDispatchInfo::weight). Failed calls always consume their full weight.
+    pub actual_weight: Option<Weight>,
+}
+
+impl PostDispatchInfo {
+    /// The weight actually consumed by a call which was charged `weight`.
+    pub fn actual_weight(&self, weight: Weight) -> Weight {
+        self.actual_weight.map_or(weight, |actual| actual.min(weight))
+    }
+}
+
+/// The result of dispatching a call: what is known about it once dispatched if it succeeded,
+/// otherwise the error `E` describing what went wrong.
+pub type DispatchResultWithPostInfo<E> = Result<PostDispatchInfo, E>;
+
+/// A trait which allows us to dispatch an incoming extrinsic to the appropriate state transition
+/// function call.
+pub trait Dispatch {
+    /// The type used to identify the caller of the function.
+    type Caller;
+    /// The state transition function call the caller is trying to access.
+    type Call;
+    /// The error returned when the call fails.
+    type Error;
+
+    /// This function takes a `caller` and the `call` they want to make, and returns a `Result`
+    /// based on the outcome of that function call.
+    fn dispatch(
+        &mut self,
+        caller: Self::Caller,
+        call: Self::Call,
+    ) -> DispatchResultWithPostInfo<Self::Error>;
+}
@@ -1,20 +1,356 @@
-use std::collections::BTreeMap;
+use crate::{
+    codec::{Decode, Encode},
+    merkle::Hash,
+    storage::{OverlayedLog, Storage, StorageMap, StorageValue, Transactional},
+    support::Weight,
+};
+use core::fmt::Debug;
+use num_traits::{CheckedAdd, CheckedSub, One, Zero};
+use serde::{Deserialize, Serialize};
+
+/// The configuration trait for the System Pallet.
+/// This controls the common types used throughout our state machine.
+pub trait Config {
+    /// A type which can identify an account in our state machine.
+    /// On a real blockchain, you would want this to be a cryptographic public key.
+    type AccountId: Ord + Clone + Debug + Encode + Decode;
+    /// A type which can be used to represent the current block number.
+    /// Usually a basic unsigned integer.
+    type BlockNumber: Zero + One + CheckedAdd + CheckedSub + Copy + Ord + Debug + Encode + Decode;
+    /// A type which can be used to keep track of the number of transactions from each account.
+    /// Usually a basic unsigned integer.
+    type Nonce: Zero + One + CheckedAdd + Copy + Eq + Debug + Encode + Decode;
+    /// The aggregated event type of the runtime, which the events of every pallet convert into.
+    type RuntimeEvent: Clone + Eq + Debug;
+    /// The error returned when dispatching a call of the runtime fails.
+    type DispatchError: Clone + Eq + Debug;
+    /// The storage backend in which each pallet keeps its state, e.g.
+    /// [`crate::storage::InMemoryStorage`].
+    type Storage: Storage + Transactional + Default + Clone + Eq + Debug;
+
+    /// The number of recent blocks whose hash is kept in storage.
+    const BLOCK_HASH_COUNT: Self::BlockNumber;
+    /// The maximum total weight of the extrinsics of a block.
+    const MAX_BLOCK_WEIGHT: Weight;
+    /// The weight of every extrinsic on top of the weight of its call, i.e. checking its
+    /// signature, charging its fee and incrementing its nonce.
+    const EXTRINSIC_BASE_WEIGHT: Weight;
+}
+
+/// The events emitted by the System Pallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T: Config> {
+    /// The extrinsic at `index` in the current block was included, but its call failed with
+    /// `error`.
+    ExtrinsicFailed { index: u32, error: T::DispatchError },
+}
+
+/// The initial state of the System Pallet, e.g. read from a `genesis.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    default,
+    bound(
+        serialize = "T::BlockNumber: Serialize",
+        deserialize = "T::BlockNumber: Deserialize<'de>"
+    )
+)]
+pub struct GenesisConfig<T: Config> {
+    /// The number of the last block before the chain starts, zero for a new chain.
+    pub block_number: T::BlockNumber,
+}
+
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        Self { block_number: T::BlockNumber::zero() }
+    }
+}
+
+/// The full state of the System Pallet at some block, see [`Pallet::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::AccountId: Serialize, T::BlockNumber: Serialize, T::Nonce: Serialize",
+    deserialize = "T::AccountId: Deserialize<'de>, T::BlockNumber: Deserialize<'de>, \
+                   T::Nonce: Deserialize<'de>"
+))]
+pub struct Snapshot<T: Config> {
+    pub block_number: T::BlockNumber,
+    /// The weight consumed by the extrinsics of the current block.
+    pub block_weight: Weight,
+    /// The nonce of every account which has one, in account order.
+    pub nonces: Vec<(T::AccountId, T::Nonce)>,
+    /// The hashes of the recent blocks, in block number order.
+    #[serde(with = "crate::merkle::hex::pairs")]
+    pub block_hashes: Vec<(T::BlockNumber, Hash)>,
+}
 
 /// This is the System Pallet.
 /// It handles low level state needed for your blockchain.
-pub struct Pallet {
-	/// The current block number.
-    block_number: u32,
-	/// A map from an account to their nonce.
-    nonce: BTreeMap<String, u32>,
-}
-
-impl Pallet {
-	/// Create a new instance of the System Pallet.
-	pub fn new() -> Self {
-        Self {
-            block_number: 0,
-            nonce: BTreeMap::new(),
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pallet<T: Config> {
+    /// The storage backend holding the state of this pallet, see the storage items below.
+    storage: T::Storage,
+    /// The events deposited during the current block.
+    events: OverlayedLog<T::RuntimeEvent>,
+}
+
+impl<T: Config> Pallet<T> {
+    /// The current block number.
+    const BLOCK_NUMBER: StorageValue<T::BlockNumber> = StorageValue::new(b"System/BlockNumber");
+    /// A map from an account to their nonce. The nonce of an account is kept even once it is
+    /// reaped, so that the extrinsics it signed before cannot be replayed if it is funded again.
+    const NONCE: StorageMap<T::AccountId, T::Nonce> = StorageMap::new(b"System/Nonce/");
+    /// The hashes of the last `BLOCK_HASH_COUNT` blocks before the current one, by block number.
+    /// Older ones are removed as new blocks come in, so this acts as a ring buffer.
+    const BLOCK_HASH: StorageMap<T::BlockNumber, Hash> = StorageMap::new(b"System/BlockHash/");
+    /// The weight consumed by the extrinsics of the current block so far.
+    const BLOCK_WEIGHT: StorageValue<Weight> = StorageValue::new(b"System/BlockWeight");
+
+    /// Create a new instance of the System Pallet.
+    pub fn new() -> Self {
+        Self::with_storage(T::Storage::default())
+    }
+
+    /// Create a new instance of the System Pallet, keeping its state in `storage`.
+    pub fn with_storage(storage: T::Storage) -> Self {
+        Self { storage, events: OverlayedLog::new() }
+    }
+
+    /// Get the storage backend holding the state of this pallet.
+    pub fn storage(&self) -> &T::Storage {
+        &self.storage
+    }
+
+    /// Get the root of the Merkle tree over all the storage entries of this pallet.
+    pub fn storage_root(&self) -> crate::merkle::Hash {
+        crate::merkle::storage_root(&self.storage)
+    }
+
+    /// Persist the changes made to the storage of this pallet since the last commit. Called by the
+    /// runtime at the end of every block.
+    pub fn commit_storage(&mut self) -> std::io::Result<()> {
+        self.storage.commit()
+    }
+
+    /// Set up the initial state of this pallet from `config`.
+    pub fn build_genesis(&mut self, config: &GenesisConfig<T>) {
+        Self::BLOCK_NUMBER.set(&mut self.storage, &config.block_number);
+    }
+
+    /// Get the full state of this pallet, e.g. to dump it as JSON.
+    pub fn snapshot(&self) -> Snapshot<T> {
+        // Entries are stored in the order of their encoded keys, which is not the order of the
+        // keys themselves, e.g. for little endian block numbers.
+        let mut nonces = Self::NONCE.iter(&self.storage);
+        nonces.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut block_hashes = Self::BLOCK_HASH.iter(&self.storage);
+        block_hashes.sort_by_key(|(number, _)| *number);
+        Snapshot {
+            block_number: self.block_number(),
+            block_weight: self.block_weight(),
+            nonces,
+            block_hashes,
+        }
+    }
+
+    /// Get the current block number.
+    pub fn block_number(&self) -> T::BlockNumber {
+        Self::BLOCK_NUMBER.get(&self.storage).unwrap_or(T::BlockNumber::zero())
+    }
+
+    /// This function can be used to increment the block number.
+    /// Increases the block number by one.
+    pub fn inc_block_number(&mut self) {
+        // A blockchain would never realistically reach the maximum block number, so a panic here is
+        // fine.
+        let block_number = self
+            .block_number()
+            .checked_add(&T::BlockNumber::one())
+            .expect("block number overflow");
+        Self::BLOCK_NUMBER.set(&mut self.storage, &block_number);
+    }
+
+    /// Get the hash of the block `number`, if it is one of the last `BLOCK_HASH_COUNT` blocks
+    /// before the current one. The hash of the current block depends on the state after it, so it
+    /// cannot be part of that state.
+    pub fn block_hash(&self, number: T::BlockNumber) -> Option<Hash> {
+        Self::BLOCK_HASH.get(&self.storage, &number)
+    }
+
+    /// Record `parent_hash` as the hash of the previous block, at the start of the current one, and
+    /// forget the hash of the block which is now more than `BLOCK_HASH_COUNT` blocks old.
+    pub fn note_parent_hash(&mut self, parent_hash: Hash) {
+        let Some(parent) = self.block_number().checked_sub(&T::BlockNumber::one()) else { return };
+        Self::BLOCK_HASH.insert(&mut self.storage, &parent, &parent_hash);
+        if let Some(oldest) = parent.checked_sub(&T::BLOCK_HASH_COUNT) {
+            Self::BLOCK_HASH.remove(&mut self.storage, &oldest);
+        }
+    }
+
+    /// Get the weight consumed by the extrinsics of the current block so far.
+    pub fn block_weight(&self) -> Weight {
+        Self::BLOCK_WEIGHT.get(&self.storage).unwrap_or(0)
+    }
+
+    /// Whether an extrinsic consuming up to `weight` fits in what is left of the current block,
+    /// i.e. whether the block would stay within `MAX_BLOCK_WEIGHT`.
+    pub fn fits_in_block(&self, weight: Weight) -> bool {
+        self.block_weight()
+            .checked_add(weight)
+            .is_some_and(|total| total <= T::MAX_BLOCK_WEIGHT)
+    }
+
+    /// Record that an extrinsic of the current block consumed `weight`.
+    pub fn consume_weight(&mut self, weight: Weight) {
+        let block_weight = self.block_weight().saturating_add(weight);
+        Self::BLOCK_WEIGHT.set(&mut self.storage, &block_weight);
+    }
+
+    /// Forget the weight consumed by the previous block. Called at the start of every block.
+    pub fn reset_block_weight(&mut self) {
+        Self::BLOCK_WEIGHT.remove(&mut self.storage);
+    }
+
+    /// Get the nonce of an account `who`.
+    pub fn get_nonce(&self, who: &T::AccountId) -> T::Nonce {
+        Self::NONCE.get(&self.storage, who).unwrap_or(T::Nonce::zero())
+    }
+
+    /// Increment the nonce of an account. This helps us keep track of how many transactions each
+    /// account has made.
+    pub fn inc_nonce(&mut self, who: &T::AccountId) {
+        let nonce = self.get_nonce(who).checked_add(&T::Nonce::one()).expect("nonce overflow");
+        Self::NONCE.insert(&mut self.storage, who, &nonce);
+    }
+
+    /// Get the events deposited so far in the current block.
+    pub fn events(&self) -> &[T::RuntimeEvent] {
+        self.events.entries()
+    }
+
+    /// Deposit an event, recording it for the current block.
+    pub fn deposit_event(&mut self, event: T::RuntimeEvent) {
+        self.events.push(event);
+    }
+
+    /// Clear the events of the previous block. Called at the start of every block.
+    pub fn reset_events(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl<T: Config> Transactional for Pallet<T> {
+    fn start_transaction(&mut self) {
+        self.storage.start_transaction();
+        self.events.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) {
+        self.storage.commit_transaction();
+        self.events.commit_transaction();
+    }
+
+    fn rollback_transaction(&mut self) {
+        self.storage.rollback_transaction();
+        self.events.rollback_transaction();
+    }
+}
+
+impl<T: Config> Default for Pallet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestConfig;
+
+    impl super::Config for TestConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = &'static str;
+        type DispatchError = &'static str;
+        type Storage = crate::storage::InMemoryStorage;
+        const BLOCK_HASH_COUNT: u32 = 2;
+        const MAX_BLOCK_WEIGHT: crate::support::Weight = 1_000;
+        const EXTRINSIC_BASE_WEIGHT: crate::support::Weight = 100;
+    }
+
+    #[test]
+    fn init_system() {
+        let mut system = super::Pallet::<TestConfig>::new();
+        assert_eq!(system.block_number(), 0);
+        assert_eq!(system.get_nonce(&"alice".to_string()), 0);
+
+        system.inc_block_number();
+        system.inc_nonce(&"alice".to_string());
+        system.inc_nonce(&"alice".to_string());
+
+        assert_eq!(system.block_number(), 1);
+        assert_eq!(system.get_nonce(&"alice".to_string()), 2);
+        assert_eq!(system.get_nonce(&"bob".to_string()), 0);
+    }
+
+    #[test]
+    fn deposit_events() {
+        let mut system = super::Pallet::<TestConfig>::new();
+        assert!(system.events().is_empty());
+
+        system.deposit_event("first");
+        system.deposit_event("second");
+        assert_eq!(system.events(), &["first", "second"]);
+
+        system.reset_events();
+        assert!(system.events().is_empty());
+    }
+
+    #[test]
+    fn block_hashes() {
+        let mut system = super::Pallet::<TestConfig>::new();
+        for n in 0..4u8 {
+            system.inc_block_number();
+            system.note_parent_hash([n; 32]);
+            assert_eq!(system.block_hash(u32::from(n)), Some([n; 32]));
         }
-	}
+        // Only the last two hashes are kept.
+        assert_eq!(system.block_hash(1), None);
+        assert_eq!(system.block_hash(2), Some([2; 32]));
+        assert_eq!(system.block_hash(3), Some([3; 32]));
+        assert_eq!(system.block_hash(4), None);
+    }
+
+    #[test]
+    fn block_weight() {
+        let mut system = super::Pallet::<TestConfig>::new();
+        assert_eq!(system.block_weight(), 0);
+        assert!(system.fits_in_block(1_000));
+        assert!(!system.fits_in_block(1_001));
+
+        system.consume_weight(600);
+        system.consume_weight(300);
+        assert_eq!(system.block_weight(), 900);
+        assert!(system.fits_in_block(100));
+        assert!(!system.fits_in_block(101));
+        assert!(!system.fits_in_block(u64::MAX));
+
+        system.reset_block_weight();
+        assert_eq!(system.block_weight(), 0);
+    }
+
+    #[test]
+    fn genesis_and_snapshot() {
+        let mut system = super::Pallet::<TestConfig>::new();
+        system.build_genesis(&super::GenesisConfig { block_number: 5 });
+        system.inc_block_number();
+        system.note_parent_hash([5; 32]);
+        system.inc_nonce(&"bob".to_string());
+        system.inc_nonce(&"alice".to_string());
+
+        let snapshot = system.snapshot();
+        assert_eq!(snapshot.block_number, 6);
+        assert_eq!(snapshot.nonces, vec![("alice".to_string(), 1), ("bob".to_string(), 1)]);
+        assert_eq!(snapshot.block_hashes, vec![(5, [5; 32])]);
+    }
 }